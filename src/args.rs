@@ -0,0 +1,183 @@
+// Command line options. These are read once at startup and
+// used to configure the first world
+
+use bevy::prelude::*;
+
+use crate::terrain::bevy_connect::WorldConfig;
+use crate::terrain::settings::GenerationSettings;
+
+// Worlds smaller than this can't fit the generated features
+const MIN_SIZE: u32 = 16;
+
+pub const USAGE: &str = "Usage: csagame [options]
+    --seed <seed>      Seed used to generate the world
+    --width <tiles>    Width of the world
+    --height <tiles>   Height of the world
+    --preset <name>    Generation settings preset from assets/worldgen
+    --load <save>      Load a saved world instead of generating one
+    --headless         Create the world without opening a window, then exit
+    --check-worldgen   Check world generation against the stored snapshots
+    --bless-worldgen   Update the stored world generation snapshots
+    --bench-worldgen   Time each generation pass on worlds of increasing size
+    --help             Show this message";
+
+#[derive(Resource, Default)]
+pub struct Args {
+    pub seed: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub preset: Option<String>,
+    pub load: Option<String>,
+    pub headless: bool,
+    pub check_worldgen: bool,
+    pub bless_worldgen: bool,
+    pub bench_worldgen: bool,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            // Every option other than the flags takes a value
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value after {}", arg))
+            };
+
+            match arg.as_str() {
+                "--seed" => parsed.seed = Some(value()?),
+                "--width" => parsed.width = Some(parse_size(&value()?)?),
+                "--height" => parsed.height = Some(parse_size(&value()?)?),
+                "--preset" => parsed.preset = Some(value()?),
+                "--load" => parsed.load = Some(value()?),
+                "--headless" => parsed.headless = true,
+                "--check-worldgen" => parsed.check_worldgen = true,
+                "--bless-worldgen" => parsed.bless_worldgen = true,
+                "--bench-worldgen" => parsed.bench_worldgen = true,
+                "--help" | "-h" => parsed.help = true,
+                _ => return Err(format!("Unknown option: {}", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    // The configuration of the first world, unset options use the defaults
+    pub fn world_config(&self) -> Result<WorldConfig, String> {
+        let mut config = WorldConfig::default();
+
+        if let Some(seed) = &self.seed {
+            config.seed = seed.clone();
+        }
+
+        if let Some(width) = self.width {
+            config.width = width;
+        }
+
+        if let Some(height) = self.height {
+            config.height = height;
+        }
+
+        if let Some(preset) = &self.preset {
+            config.settings = GenerationSettings::load_preset(preset)?;
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_size(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(size) if size >= MIN_SIZE => Ok(size),
+        Ok(_) => Err(format!("World size must be at least {}", MIN_SIZE)),
+        Err(_) => Err(format!("Invalid world size: {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn no_options() {
+        let args = parse(&[]).unwrap();
+
+        assert!(args.seed.is_none() && args.width.is_none() && args.load.is_none());
+        assert!(!args.headless && !args.help);
+    }
+
+    #[test]
+    fn values_and_flags() {
+        let args = parse(&[
+            "--seed",
+            "hello world",
+            "--width",
+            "256",
+            "--height",
+            "128",
+            "--preset",
+            "caverns",
+            "--headless",
+            "--check-worldgen",
+        ])
+        .unwrap();
+
+        assert_eq!(args.seed.as_deref(), Some("hello world"));
+        assert_eq!((args.width, args.height), (Some(256), Some(128)));
+        assert_eq!(args.preset.as_deref(), Some("caverns"));
+        assert!(args.headless && args.check_worldgen);
+        assert!(!args.bless_worldgen && !args.bench_worldgen);
+    }
+
+    #[test]
+    fn later_values_replace_earlier_ones() {
+        let args = parse(&["--seed", "a", "--seed", "b"]).unwrap();
+
+        assert_eq!(args.seed.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn help() {
+        assert!(parse(&["--help"]).unwrap().help);
+        assert!(parse(&["-h"]).unwrap().help);
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(
+            parse(&["--headless", "--load"]).err(),
+            Some("Missing value after --load".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_eq!(
+            parse(&["--seed", "a", "--fast"]).err(),
+            Some("Unknown option: --fast".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        assert_eq!(
+            parse(&["--width", "wide"]).err(),
+            Some("Invalid world size: wide".to_string())
+        );
+        assert_eq!(
+            parse(&["--height", "-5"]).err(),
+            Some("Invalid world size: -5".to_string())
+        );
+        assert_eq!(
+            parse(&["--width", "15"]).err(),
+            Some(format!("World size must be at least {}", MIN_SIZE))
+        );
+        assert_eq!(parse(&["--width", "16"]).unwrap().width, Some(16));
+    }
+}
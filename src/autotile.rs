@@ -0,0 +1,599 @@
+// Rule based auto-tiling. Each tileset declares which cell to use for each
+// arrangement of neighbours, and the lookup for every bitmask is derived from that
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use crate::surrounds::Surrounds;
+use crate::terrain::TOTAL_LAYERS;
+use crate::tile::*;
+
+// Which neighbours change the cell a tile uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    // Only the four sides, 16 cases
+    Edges,
+
+    // The sides, plus corners where both sides next to them are solid, 47 cases
+    Blob,
+
+    // Only the tiles either side, 4 cases
+    Row,
+}
+
+// Each corner along with the sides next to it
+const CORNERS: [(Surrounds, Surrounds, Surrounds); 4] = [
+    (Surrounds::TL, Surrounds::TM, Surrounds::ML),
+    (Surrounds::TR, Surrounds::TM, Surrounds::MR),
+    (Surrounds::BL, Surrounds::BM, Surrounds::ML),
+    (Surrounds::BR, Surrounds::BM, Surrounds::MR),
+];
+
+impl Connectivity {
+    // Drop the neighbours that don't affect which cell is used
+    pub fn reduce(&self, surrounds: Surrounds) -> Surrounds {
+        let edges = surrounds & (Surrounds::TM | Surrounds::ML | Surrounds::MR | Surrounds::BM);
+
+        match self {
+            Self::Edges => edges,
+            Self::Blob => CORNERS
+                .iter()
+                .filter(|(corner, a, b)| surrounds.contains(*corner | *a | *b))
+                .fold(edges, |reduced, (corner, _, _)| reduced | *corner),
+            Self::Row => surrounds & (Surrounds::ML | Surrounds::MR),
+        }
+    }
+
+    // Neighbours that would change the cell if they were joined up with, which are
+    // the ones a tile shows an edge against
+    pub fn edges(&self, connections: Surrounds) -> Surrounds {
+        let reduced = self.reduce(connections);
+
+        (0..8)
+            .map(|i| Surrounds::from_bits_truncate(1 << i))
+            .filter(|n| self.reduce(connections | *n) != reduced)
+            .fold(Surrounds::empty(), |edges, n| edges | n)
+    }
+}
+
+pub struct AutotileLayout {
+    pub name: &'static str,
+    pub connectivity: Connectivity,
+
+    // Neighbours as Surrounds flag names and the tileset cell used for them
+    pub rules: &'static [(&'static str, (u32, u32))],
+
+    // Derived from the rules on first use
+    table: OnceLock<[(u32, u32); 256]>,
+}
+
+impl AutotileLayout {
+    const fn new(
+        name: &'static str,
+        connectivity: Connectivity,
+        rules: &'static [(&'static str, (u32, u32))],
+    ) -> Self {
+        Self {
+            name,
+            connectivity,
+            rules,
+            table: OnceLock::new(),
+        }
+    }
+
+    // Offset of the cell to use, from the top left of the tile's cells
+    pub fn offset(&self, surrounds: Surrounds) -> (u32, u32) {
+        let table = self.table.get_or_init(|| {
+            self.build()
+                .unwrap_or_else(|e| panic!("Invalid {} autotile layout: {}", self.name, e))
+        });
+
+        table[surrounds.bits() as usize]
+    }
+
+    // The cells a tile using this layout needs
+    pub fn cells(&self) -> impl Iterator<Item = (u32, u32)> {
+        self.rules.iter().map(|(_, cell)| *cell)
+    }
+
+    // Work out the cell for every bitmask, making sure each one has exactly one rule
+    pub fn build(&self) -> Result<[(u32, u32); 256], String> {
+        let mut cells = HashMap::new();
+
+        for &(names, cell) in self.rules {
+            let surrounds = Surrounds::parse(names)?;
+
+            if self.connectivity.reduce(surrounds) != surrounds {
+                return Err(format!("Rule \"{}\" can never match", names));
+            }
+
+            if cells.insert(surrounds, cell).is_some() {
+                return Err(format!("More than one rule for \"{}\"", names));
+            }
+        }
+
+        let mut table = [(0, 0); 256];
+
+        for (bits, entry) in table.iter_mut().enumerate() {
+            let surrounds = self
+                .connectivity
+                .reduce(Surrounds::from_bits_truncate(bits as u8));
+
+            *entry = *cells
+                .get(&surrounds)
+                .ok_or_else(|| format!("No rule for \"{}\"", surrounds.names()))?;
+        }
+
+        Ok(table)
+    }
+}
+
+// Uses corner pieces for the inside of bends
+pub static BLOB: AutotileLayout = AutotileLayout::new(
+    "blob",
+    Connectivity::Blob,
+    &[
+        ("", (6, 0)),
+        ("TM", (4, 2)),
+        ("ML", (5, 1)),
+        ("MR", (3, 1)),
+        ("BM", (4, 0)),
+        ("TM ML", (15, 1)),
+        ("TM MR", (14, 1)),
+        ("ML MR", (6, 2)),
+        ("TM BM", (6, 1)),
+        ("ML BM", (15, 0)),
+        ("MR BM", (14, 0)),
+        ("TL TM ML", (2, 2)),
+        ("TM TR MR", (0, 2)),
+        ("TM ML MR", (21, 1)),
+        ("TM ML BM", (21, 0)),
+        ("TM MR BM", (20, 1)),
+        ("ML MR BM", (20, 0)),
+        ("ML BL BM", (2, 0)),
+        ("MR BM BR", (0, 0)),
+        ("TL TM ML MR", (19, 1)),
+        ("TM TR ML MR", (17, 1)),
+        ("TL TM ML BM", (17, 0)),
+        ("TM TR MR BM", (18, 1)),
+        ("TM ML MR BM", (4, 1)),
+        ("TM ML BL BM", (19, 0)),
+        ("ML MR BL BM", (16, 0)),
+        ("TM MR BM BR", (16, 1)),
+        ("ML MR BM BR", (18, 0)),
+        ("TL TM TR ML MR", (1, 2)),
+        ("TL TM ML MR BM", (10, 1)),
+        ("TM TR ML MR BM", (9, 1)),
+        ("TL TM ML BL BM", (2, 1)),
+        ("TM ML MR BL BM", (10, 0)),
+        ("TM TR MR BM BR", (0, 1)),
+        ("TM ML MR BM BR", (9, 0)),
+        ("ML MR BL BM BR", (1, 0)),
+        ("TL TM TR ML MR BM", (11, 0)),
+        ("TL TM ML MR BL BM", (13, 1)),
+        ("TM TR ML MR BL BM", (12, 1)),
+        ("TL TM ML MR BM BR", (12, 0)),
+        ("TM TR ML MR BM BR", (13, 0)),
+        ("TM ML MR BL BM BR", (11, 1)),
+        ("TL TM TR ML MR BL BM", (7, 0)),
+        ("TL TM TR ML MR BM BR", (8, 0)),
+        ("TL TM ML MR BL BM BR", (7, 1)),
+        ("TM TR ML MR BL BM BR", (8, 1)),
+        ("TL TM TR ML MR BL BM BR", (1, 1)),
+    ],
+);
+
+// For decor that grows in runs along the ground
+pub static ROW: AutotileLayout = AutotileLayout::new(
+    "row",
+    Connectivity::Row,
+    &[
+        ("", (0, 0)),
+        ("MR", (1, 0)),
+        ("ML MR", (2, 0)),
+        ("ML", (3, 0)),
+    ],
+);
+
+// For transitions, which only depend on the sides
+pub static EDGES: AutotileLayout = AutotileLayout::new(
+    "edges",
+    Connectivity::Edges,
+    &[
+        ("", (0, 0)),
+        ("TM", (1, 0)),
+        ("ML", (2, 0)),
+        ("TM ML", (3, 0)),
+        ("MR", (4, 0)),
+        ("TM MR", (5, 0)),
+        ("ML MR", (6, 0)),
+        ("TM ML MR", (7, 0)),
+        ("BM", (8, 0)),
+        ("TM BM", (9, 0)),
+        ("ML BM", (10, 0)),
+        ("TM ML BM", (11, 0)),
+        ("MR BM", (12, 0)),
+        ("TM MR BM", (13, 0)),
+        ("ML MR BM", (14, 0)),
+        ("TM ML MR BM", (15, 0)),
+    ],
+);
+
+pub static LAYOUTS: [&AutotileLayout; 3] = [&BLOB, &ROW, &EDGES];
+
+pub struct Tileset {
+    pub path: &'static str,
+
+    // Tiles keep their own offsets without one
+    pub layout: Option<&'static AutotileLayout>,
+}
+
+// The layout a tile is auto-tiled with. Tiles that don't join up with anything,
+// such as trees and multi-tiles, keep their own offsets
+pub fn layout_of(id: TileId) -> Option<&'static AutotileLayout> {
+    let desc = TileDescriptor::from_id(id);
+
+    TILESETS[id.layer()]
+        .layout
+        .filter(|_| desc.connections.group != ConnectGroup::None)
+}
+
+// The tileset used by each layer
+pub static TILESETS: [Tileset; TOTAL_LAYERS] = [
+    Tileset {
+        path: "Tiles.png",
+        layout: Some(&BLOB),
+    },
+    Tileset {
+        path: "MiddlegroundTiles.png",
+        layout: Some(&ROW),
+    },
+    Tileset {
+        path: "BackgroundTiles.png",
+        layout: Some(&BLOB),
+    },
+];
+
+// Stable hash of a position, so the same variants are picked every time a world is loaded
+fn position_hash(x: u32, y: u32) -> u32 {
+    let mut hash = (x as u64) << 32 | y as u64;
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+
+    hash as u32
+}
+
+impl Tile {
+    // The sprite drawn for this tile, which may be one of its variants
+    pub fn get_sprite(&self, x: u32, y: u32) -> Sprite {
+        let usual = Sprite {
+            index: self.get_texture_index(),
+            ..Default::default()
+        };
+
+        if !self.shape.is_full() {
+            return usual;
+        }
+
+        let Some(offset) = self.texture_offset else {
+            return usual;
+        };
+
+        let Some(variants) = variants().get(&(self.id, offset)) else {
+            return usual;
+        };
+
+        let total = USUAL_WEIGHT + variants.iter().map(|v| v.weight).sum::<u32>();
+        let mut roll = position_hash(x, y) % total;
+
+        if roll < USUAL_WEIGHT {
+            return usual;
+        }
+
+        roll -= USUAL_WEIGHT;
+
+        for &variant in variants {
+            if roll < variant.weight {
+                let tile = Tile {
+                    texture_offset: Some(variant.cell.unwrap_or(offset)),
+                    ..*self
+                };
+
+                return Sprite {
+                    index: tile.get_texture_index(),
+                    flip_x: variant.flip_x,
+                    flip_y: variant.flip_y,
+                };
+            }
+
+            roll -= variant.weight;
+        }
+
+        unreachable!("The roll is always less than the total weight")
+    }
+}
+
+// The variants of each tile, by the tile and the cell they replace
+type VariantTable = BTreeMap<(TileId, (u32, u32)), Vec<&'static Variant>>;
+
+// Derived from the tile descriptors on first use
+static VARIANTS: OnceLock<VariantTable> = OnceLock::new();
+
+fn variants() -> &'static VariantTable {
+    VARIANTS
+        .get_or_init(|| build_variants().unwrap_or_else(|e| panic!("Invalid tile variant: {}", e)))
+}
+
+// Parse every variant's configuration, checking its layout can produce it
+fn build_variants() -> Result<VariantTable, String> {
+    let mut table = VariantTable::new();
+
+    for desc in TileDescriptor::all() {
+        if desc.variants.is_empty() {
+            continue;
+        }
+
+        let layout = layout_of(desc.id)
+            .ok_or_else(|| format!("{} has variants but isn't auto-tiled", desc.name))?;
+
+        for variant in desc.variants {
+            let config = Surrounds::parse(variant.config)?;
+
+            if layout.connectivity.reduce(config) != config {
+                return Err(format!(
+                    "{} has a variant for \"{}\", which the {} layout never uses",
+                    desc.name, variant.config, layout.name
+                ));
+            }
+
+            if variant.weight == 0 {
+                return Err(format!("{} has a variant that is never used", desc.name));
+            }
+
+            table
+                .entry((desc.id, layout.offset(config)))
+                .or_default()
+                .push(variant);
+        }
+    }
+
+    Ok(table)
+}
+
+// Check every layout is complete and every auto-tiled tile has all the cells it needs
+pub fn validate() -> Result<(), String> {
+    for layout in LAYOUTS {
+        layout
+            .build()
+            .map_err(|e| format!("Invalid {} autotile layout: {}", layout.name, e))?;
+    }
+
+    build_variants()?;
+
+    for desc in TileDescriptor::all() {
+        let Some(layout) = layout_of(desc.id) else {
+            continue;
+        };
+
+        let (x, y) = (
+            desc.tileset_position % TILESET_SIZE.0,
+            desc.tileset_position / TILESET_SIZE.0,
+        );
+
+        let variant_cells = desc.variants.iter().filter_map(|v| v.cell);
+
+        let shape_cells = TileShape::SHAPED
+            .iter()
+            .filter(|_| desc.shaped)
+            .filter_map(|shape| shape.cell());
+
+        let transition_cells = desc.connections.transition.iter().flat_map(|t| {
+            EDGES
+                .cells()
+                .map(move |cell| (cell.0 + t.offset.0, cell.1 + t.offset.1))
+        });
+
+        for cell in layout
+            .cells()
+            .chain(variant_cells)
+            .chain(shape_cells)
+            .chain(transition_cells)
+        {
+            if x + cell.0 >= TILESET_SIZE.0 || y + cell.1 >= TILESET_SIZE.1 {
+                return Err(format!(
+                    "{} uses cell {:?} of the {} layout, which is outside its tileset",
+                    desc.name, cell, layout.name
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surrounds(names: &str) -> Surrounds {
+        Surrounds::parse(names).unwrap()
+    }
+
+    #[test]
+    fn blob_rules_match_themselves() {
+        let table = BLOB.build().unwrap();
+
+        for &(names, cell) in BLOB.rules {
+            assert_eq!(table[surrounds(names).bits() as usize], cell, "{}", names);
+        }
+    }
+
+    #[test]
+    fn blob_corners_need_both_sides() {
+        let table = BLOB.build().unwrap();
+
+        let expected = [
+            (0, (6, 0)),
+            (21, (3, 1)),   // TL TR MR -> MR
+            (54, (0, 2)),   // TM TR MR BL -> TM TR MR
+            (174, (15, 1)), // TM TR ML BL BR -> TM ML
+            (207, (17, 0)), // TL TM TR ML BM BR -> TL TM ML BM
+            (221, (18, 0)), // TL TR ML MR BM BR -> ML MR BM BR
+            (255, (1, 1)),
+        ];
+
+        for (bits, cell) in expected {
+            assert_eq!(table[bits], cell, "bitmask {}", bits);
+        }
+
+        // Every bitmask uses the cell of its reduced form
+        for (bits, cell) in table.iter().enumerate() {
+            let reduced = Connectivity::Blob.reduce(Surrounds::from_bits_truncate(bits as u8));
+            assert_eq!(*cell, table[reduced.bits() as usize], "bitmask {}", bits);
+        }
+    }
+
+    #[test]
+    fn blob_has_47_cells() {
+        let mut cells: Vec<(u32, u32)> = BLOB.build().unwrap().to_vec();
+        cells.sort();
+        cells.dedup();
+
+        assert_eq!(cells.len(), 47);
+    }
+
+    #[test]
+    fn row_only_uses_the_sides() {
+        let table = ROW.build().unwrap();
+
+        let expected = [
+            ("", (0, 0)),
+            ("TL TM TR BL BM BR", (0, 0)),
+            ("MR BM", (1, 0)),
+            ("TL ML MR BR", (2, 0)),
+            ("ML BL BM", (3, 0)),
+        ];
+
+        for (names, cell) in expected {
+            assert_eq!(table[surrounds(names).bits() as usize], cell, "{}", names);
+        }
+    }
+
+    #[test]
+    fn edges_are_what_changes_the_cell() {
+        let blob = Connectivity::Blob;
+
+        assert_eq!(blob.edges(surrounds("")), surrounds("TM ML MR BM"));
+        assert_eq!(blob.edges(surrounds("TM ML")), surrounds("TL MR BM"));
+        assert_eq!(
+            blob.edges(surrounds("TL TM TR ML MR BL BM BR")),
+            Surrounds::empty()
+        );
+
+        // Corners only matter when both sides next to them are joined up with
+        assert_eq!(blob.edges(surrounds("TR MR")), surrounds("TM ML BM"));
+        assert_eq!(
+            Connectivity::Edges.edges(surrounds("TM ML")),
+            surrounds("MR BM")
+        );
+    }
+
+    #[test]
+    fn every_layout_is_used() {
+        let transitions = TileDescriptor::all()
+            .iter()
+            .any(|desc| desc.connections.transition.is_some());
+
+        for layout in LAYOUTS {
+            let tileset = TILESETS
+                .iter()
+                .any(|t| t.layout.map_or(false, |l| std::ptr::eq(l, layout)));
+
+            assert!(
+                tileset || (std::ptr::eq(layout, &EDGES) && transitions),
+                "{}",
+                layout.name
+            );
+        }
+    }
+
+    #[test]
+    fn only_tiles_that_join_up_are_auto_tiled() {
+        assert!(layout_of(TileId::SurfaceDecor(SurfaceDecor::GrassSmall)).is_some());
+        assert!(layout_of(TileId::Ground(Ground::Stone)).is_some());
+
+        assert!(layout_of(TileId::Tree(Tree::Wood)).is_none());
+        assert!(layout_of(TileId::SurfaceDecor(SurfaceDecor::RockPile)).is_none());
+        assert!(layout_of(TileId::Empty).is_none());
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        let build = |rules| AutotileLayout::new("test", Connectivity::Blob, rules).build();
+
+        assert_eq!(
+            build(&[("", (0, 0)), ("TL", (1, 0))]).unwrap_err(),
+            "Rule \"TL\" can never match"
+        );
+        assert_eq!(
+            build(&[("TM", (0, 0)), ("TM", (1, 0))]).unwrap_err(),
+            "More than one rule for \"TM\""
+        );
+        assert!(build(&[("", (0, 0))])
+            .unwrap_err()
+            .starts_with("No rule for"));
+    }
+
+    #[test]
+    fn variants_replace_layout_cells() {
+        for ((id, cell), variants) in build_variants().unwrap() {
+            let layout = TILESETS[id.layer()].layout.unwrap();
+
+            assert!(layout.cells().any(|c| c == cell), "{:?} at {:?}", id, cell);
+            assert!(!variants.is_empty());
+        }
+    }
+
+    #[test]
+    fn every_variant_is_picked() {
+        let interior = BLOB.offset(surrounds("TL TM TR ML MR BL BM BR"));
+
+        for id in [TileId::Ground(Ground::Dirt), TileId::Ground(Ground::Stone)] {
+            let tile = Tile::new(id, Some(interior));
+
+            let sprites: Vec<Sprite> = (0..32)
+                .flat_map(|x| (0..32).map(move |y| tile.get_sprite(x, y)))
+                .collect();
+
+            let usual = Sprite {
+                index: tile.get_texture_index(),
+                ..Default::default()
+            };
+            assert!(sprites.contains(&usual), "{:?}", id);
+
+            let variants = &variants()[&(id, interior)];
+            assert!(variants.iter().any(|v| v.cell.is_some()), "{:?}", id);
+
+            for variant in variants {
+                let cell = variant.cell.unwrap_or(interior);
+
+                let expected = Sprite {
+                    index: Tile::new(id, Some(cell)).get_texture_index(),
+                    flip_x: variant.flip_x,
+                    flip_y: variant.flip_y,
+                };
+
+                assert!(sprites.contains(&expected), "{:?} {:?}", id, cell);
+            }
+        }
+    }
+
+    #[test]
+    fn layouts_and_tiles_are_valid() {
+        assert_eq!(validate(), Ok(()));
+    }
+}
@@ -1,48 +1,241 @@
+use bevy::input::mouse::MouseWheel;
 use bevy::prelude::*;
 
+use crate::character::CharacterId;
+use crate::input::{Action, ActionState};
+use crate::terrain::bevy_connect::{TilemapLayer, TILE_SIZE};
+use crate::terrain::{Terrain, FRONT};
+
 pub const CAMERA_MOVE_SPEED: f32 = 250.0; // Distance per second
+pub const CAMERA_FOLLOW_SPEED: f32 = 5.0; // Fraction of the distance covered per second
+
+// Zoom is the number of screen pixels per world pixel,
+// keeping it whole makes sure sprites are never distorted
+pub const MIN_ZOOM: u32 = 1;
+pub const MAX_ZOOM: u32 = 6;
+pub const DEFAULT_ZOOM: u32 = 2;
+
+#[derive(Component, Deref, DerefMut)]
+pub struct Zoom(pub u32);
+
+// Entities with this component will be followed by the camera
+#[derive(Component)]
+pub struct CameraTarget;
 
 #[derive(Resource, Deref, DerefMut)]
 pub struct CursorPos(pub Vec2);
 
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CameraSystem {
+    Movement,
+
+    // Runs once the camera has moved so the cursor matches the view
+    Cursor,
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CursorPos(Vec2::new(f32::INFINITY, f32::INFINITY)))
+            .add_startup_system(setup_camera)
+            .add_system_set(
+                SystemSet::new()
+                    .label(CameraSystem::Movement)
+                    .with_system(move_camera)
+                    .with_system(zoom_camera)
+                    .with_system(select_camera_target)
+                    .with_system(follow_camera_target.after(move_camera))
+                    .with_system(clamp_camera.after(follow_camera_target).after(zoom_camera)),
+            )
+            .add_system(
+                update_cursor_pos
+                    .label(CameraSystem::Cursor)
+                    .after(CameraSystem::Movement),
+            );
+    }
+}
+
 // Contructs the camera
 pub fn setup_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle {
-        transform: Transform::default().with_scale(Vec3::new(0.5, 0.5, 1.0)),
-        ..Default::default()
-    });
+    let scale = 1.0 / DEFAULT_ZOOM as f32;
+
+    commands
+        .spawn(Camera2dBundle {
+            transform: Transform::default().with_scale(Vec3::new(scale, scale, 1.0)),
+            ..Default::default()
+        })
+        .insert(Zoom(DEFAULT_ZOOM));
 }
 
-// Moves the camera in response to WASD input
+// Moves the camera in response to the pan actions
 pub fn move_camera(
-    input: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    actions: Res<ActionState>,
     time: Res<Time>,
     mut query: Query<&mut Transform, With<Camera2d>>,
+    target_query: Query<Entity, With<CameraTarget>>,
 ) {
     // Speed to rounded to position is always whole - prevents half-pixels
     let speed = (time.delta_seconds() * CAMERA_MOVE_SPEED).round();
     let mut dir = Vec3::new(0.0, 0.0, 0.0);
     let mut transform = query.single_mut();
 
-    if input.pressed(KeyCode::W) {
+    if actions.pressed(Action::PanUp) {
         dir += Vec3::new(0.0, 1.0, 0.0);
     }
 
-    if input.pressed(KeyCode::A) {
+    if actions.pressed(Action::PanLeft) {
         dir += Vec3::new(-1.0, 0.0, 0.0);
     }
 
-    if input.pressed(KeyCode::S) {
+    if actions.pressed(Action::PanDown) {
         dir += Vec3::new(0.0, -1.0, 0.0);
     }
 
-    if input.pressed(KeyCode::D) {
+    if actions.pressed(Action::PanRight) {
         dir += Vec3::new(1.0, 0.0, 0.0);
     }
 
     if dir != Vec3::ZERO {
         transform.translation += dir.normalize() * speed;
+
+        // Manual movement stops the camera from following anything
+        for entity in target_query.iter() {
+            commands.entity(entity).remove::<CameraTarget>();
+        }
+    }
+}
+
+// Zooms in whole steps in response to the mouse wheel and zoom actions
+pub fn zoom_camera(
+    actions: Res<ActionState>,
+    mut wheel: EventReader<MouseWheel>,
+    mut query: Query<(&mut Transform, &mut Zoom), With<Camera2d>>,
+) {
+    let mut steps: i32 = wheel.iter().map(|e| e.y.signum() as i32).sum();
+
+    if actions.just_pressed(Action::ZoomIn) {
+        steps += 1;
+    }
+
+    if actions.just_pressed(Action::ZoomOut) {
+        steps -= 1;
+    }
+
+    if steps == 0 {
+        return;
+    }
+
+    let (mut transform, mut zoom) = query.single_mut();
+
+    **zoom = (**zoom as i32 + steps).clamp(MIN_ZOOM as i32, MAX_ZOOM as i32) as u32;
+
+    let scale = 1.0 / **zoom as f32;
+    transform.scale = Vec3::new(scale, scale, 1.0);
+}
+
+// Toggles following the character closest to the cursor
+pub fn select_camera_target(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    cursor: Res<CursorPos>,
+    char_query: Query<(Entity, &Transform), With<CharacterId>>,
+    target_query: Query<Entity, With<CameraTarget>>,
+) {
+    if !actions.just_pressed(Action::Follow) {
+        return;
+    }
+
+    // Stop following if something was already being followed
+    if !target_query.is_empty() {
+        for entity in target_query.iter() {
+            commands.entity(entity).remove::<CameraTarget>();
+        }
+
+        return;
     }
+
+    let closest = char_query.iter().min_by(|a, b| {
+        let a = a.1.translation.truncate().distance_squared(cursor.0);
+        let b = b.1.translation.truncate().distance_squared(cursor.0);
+
+        a.total_cmp(&b)
+    });
+
+    if let Some((entity, _)) = closest {
+        commands.entity(entity).insert(CameraTarget);
+    }
+}
+
+// Smoothly moves the camera towards its target
+pub fn follow_camera_target(
+    time: Res<Time>,
+    mut cam_query: Query<(&mut Transform, &Zoom), With<Camera2d>>,
+    target_query: Query<&Transform, (With<CameraTarget>, Without<Camera2d>)>,
+) {
+    let Some(target) = target_query.iter().next() else {
+        return;
+    };
+
+    let (mut transform, zoom) = cam_query.single_mut();
+
+    let t = (time.delta_seconds() * CAMERA_FOLLOW_SPEED).min(1.0);
+    let goal = target
+        .translation
+        .truncate()
+        .extend(transform.translation.z);
+
+    // clamp_camera rounds to whole screen pixels, so smaller steps would
+    // never arrive. Moving at least a pixel and snapping once within half
+    // of one stops the camera resting short of its target
+    let pixel = 1.0 / **zoom as f32;
+    let offset = goal - transform.translation;
+
+    if offset.length() < pixel / 2.0 {
+        transform.translation = goal;
+    } else {
+        transform.translation += (offset * t).clamp_length_min(pixel.min(offset.length()));
+    }
+}
+
+// Keeps the view inside the terrain. If the world is smaller
+// than the view along an axis it is centred on that axis instead
+pub fn clamp_camera(
+    windows: Res<Windows>,
+    terrain: Res<Terrain>,
+    mut cam_query: Query<(&mut Transform, &Zoom), With<Camera2d>>,
+    tm_query: Query<(&TilemapLayer, &Transform), Without<Camera2d>>,
+) {
+    let Some((_, tm_transform)) = tm_query.iter().find(|(layer, _)| layer.0 == FRONT) else {
+        return;
+    };
+
+    let window = windows.primary();
+    let (mut transform, zoom) = cam_query.single_mut();
+
+    // Tiles are centred on their position, so the world starts half a tile early
+    let tile_size = Vec2::new(TILE_SIZE.x, TILE_SIZE.y);
+    let min = tm_transform.translation.truncate() - tile_size / 2.0;
+    let max = min + Vec2::new(terrain.width as f32, terrain.height as f32) * tile_size;
+
+    let half_view = Vec2::new(window.width(), window.height()) / (2.0 * **zoom as f32);
+
+    let clamp_axis = |pos: f32, min: f32, max: f32, half: f32| {
+        if max - min <= half * 2.0 {
+            (min + max) / 2.0
+        } else {
+            pos.clamp(min + half, max - half)
+        }
+    };
+
+    transform.translation.x = clamp_axis(transform.translation.x, min.x, max.x, half_view.x);
+    transform.translation.y = clamp_axis(transform.translation.y, min.y, max.y, half_view.y);
+
+    // Only positions on whole screen pixels prevent half-pixels
+    let zoom = **zoom as f32;
+    transform.translation.x = (transform.translation.x * zoom).round() / zoom;
+    transform.translation.y = (transform.translation.y * zoom).round() / zoom;
 }
 
 pub fn update_cursor_pos(
@@ -3,6 +3,9 @@ use bevy_ecs_tilemap::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 use crate::terrain::bevy_connect::TilemapLayer;
+use crate::terrain::changes::TileChanged;
+use crate::terrain::{Terrain, FRONT};
+use crate::tile::TileShape;
 
 // World Collision Detection System:
 // This system handles collisions between the world and
@@ -22,9 +25,52 @@ pub struct WorldCollider {
     pub enabled_colliders: Vec<Entity>,
 }
 
+// Collider matching a tile's shape, centred on the tile
+fn shape_collider(shape: TileShape) -> Collider {
+    if shape.is_full() {
+        return Collider::cuboid(4.0, 4.0);
+    }
+
+    let points: Vec<Vec2> = shape
+        .outline()
+        .into_iter()
+        .map(|(x, y)| (Vec2::new(x, y) - Vec2::splat(0.5)) * 8.0)
+        .collect();
+
+    Collider::convex_hull(&points).expect("Tile shapes are never flat")
+}
+
+// Tiles keep their collider while they are in range, so it is removed when
+// the tile's shape changes and update_colliders adds one with the new shape
+pub fn invalidate_colliders(
+    mut commands: Commands,
+    mut events: EventReader<TileChanged>,
+    tm_query: Query<(&TileStorage, &TilemapLayer)>,
+    col_tile_query: Query<(), (With<Collider>, With<TilePos>)>,
+) {
+    let Some((tm_storage, _)) = tm_query.iter().find(|(_, layer)| layer.0 == FRONT) else {
+        return;
+    };
+
+    for change in events.iter() {
+        if change.layer != FRONT || change.old.shape == change.new.shape {
+            continue;
+        }
+
+        let pos = TilePos::new(change.pos.0, change.pos.1);
+
+        if let Some(entity) = tm_storage.get(&pos)
+            && col_tile_query.contains(entity)
+        {
+            commands.entity(entity).remove::<Collider>();
+        }
+    }
+}
+
 // Update the current active colliders
 pub fn update_colliders(
     mut commands: Commands,
+    terrain: Res<Terrain>,
     mut col_query: Query<(&mut WorldCollider, &Transform)>,
     mut tm_query: Query<(
         &TilemapSize,
@@ -36,11 +82,13 @@ pub fn update_colliders(
     tile_query: Query<&Transform, With<TilePos>>,
     col_tile_query: Query<&Collider, With<TilePos>>,
 ) {
-    // We only work with the Foreground layer
-    let (tm_size, tm_grid_size, tm_storage, tm_layer, tm_transform) =
-        tm_query.iter_mut().next().expect("No foreground tilemap");
-
-    assert!(tm_layer.0 == 0);
+    // We only work with the Foreground layer, which doesn't exist while the world generates
+    let Some((tm_size, tm_grid_size, tm_storage, _, tm_transform)) = tm_query
+        .iter_mut()
+        .find(|(_, _, _, layer, _)| layer.0 == FRONT)
+    else {
+        return;
+    };
 
     for (mut col, transform) in col_query.iter_mut() {
         let pos = {
@@ -88,8 +136,9 @@ pub fn update_colliders(
                         let tile = commands.get_entity(tile_entity);
 
                         if let Some(mut tile) = tile {
-                            tile
-                                .insert(Collider::cuboid(4.0, 4.0))
+                            let shape = terrain.layers[FRONT][(pos.x, pos.y)].shape;
+
+                            tile.insert(shape_collider(shape))
                                 .insert(TransformBundle::from(*tile_transform));
                         }
                     }
@@ -5,7 +5,45 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 use self::animation::*;
-use self::collision::WorldCollider;
+use self::collision::*;
+use crate::state::AppState;
+use crate::terrain::bevy_connect::TerrainSystem;
+
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharacterSystem {
+    Animation,
+    Collision,
+}
+
+pub struct CharacterPlugin;
+
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_sprite_sheets)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_animations.label(CharacterSystem::Animation))
+                    .with_system(
+                        update_colliders
+                            .label(CharacterSystem::Collision)
+                            .after(TerrainSystem::Generation),
+                    ),
+            )
+            // Tiles can change while paused, so this always runs
+            .add_system(invalidate_colliders.before(CharacterSystem::Collision))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(pause_physics))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(resume_physics));
+    }
+}
+
+// Characters stay where they are while paused
+fn pause_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = false;
+}
+
+fn resume_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = true;
+}
 
 #[derive(Bundle)]
 pub struct CharacterBundle {
@@ -53,6 +91,7 @@ pub enum CharacterId {
 // Same as TileDescriptor system
 pub struct CharacterDesc {
     pub id: CharacterId,
+    pub name: &'static str,
     pub sprite_sheet: &'static str,
     pub sprite_size: Vec2,
     pub sheet_size: (usize, usize),
@@ -64,8 +103,21 @@ impl CharacterDesc {
         &Self::DESCRIPTORS[id as usize]
     }
 
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        Self::all().iter().find(|desc| desc.name == name)
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::all().iter().map(|desc| desc.name)
+    }
+
+    pub fn all() -> &'static [Self] {
+        &Self::DESCRIPTORS
+    }
+
     const DESCRIPTORS: [Self; 1] = [Self {
         id: CharacterId::HumanMale,
+        name: "HumanMale",
         sprite_sheet: "Characters/HumanMale1.png",
         sprite_size: Vec2::new(16.0, 26.0),
         sheet_size: (8, 3),
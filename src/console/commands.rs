@@ -0,0 +1,384 @@
+// Commands available in the console by default
+
+use std::str::FromStr;
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_prototype_debug_lines::DebugLines;
+
+use super::{Console, ConsoleCommand, ConsoleRegistry};
+use crate::camera::CameraTarget;
+use crate::character::animation::SpriteSheetHandles;
+use crate::character::{CharacterBundle, CharacterDesc};
+use crate::input::{Action, ActionState, Rebinding};
+use crate::player::PathState;
+use crate::terrain::bevy_connect::{RegenerateWorld, TilemapLayer, WorldConfig};
+use crate::terrain::changes::ChangeCause;
+use crate::terrain::node::PathNode;
+use crate::terrain::settings::GenerationSettings;
+use crate::terrain::*;
+use crate::tile::*;
+
+// Parse the argument at index i
+fn arg<T: FromStr>(args: &[&str], i: usize, name: &str) -> Result<T, String> {
+    let value = args
+        .get(i)
+        .ok_or_else(|| format!("Missing argument: {}", name))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {}: {}", name, value))
+}
+
+// Transform of the foreground tilemap, used to convert tile positions to the world
+fn front_transform(world: &mut World) -> Result<Transform, String> {
+    let mut query = world.query::<(&TilemapLayer, &Transform)>();
+
+    query
+        .iter(world)
+        .find(|(layer, _)| layer.0 == FRONT)
+        .map(|(_, transform)| *transform)
+        .ok_or_else(|| "No foreground tilemap".to_string())
+}
+
+// Positions given to commands have to be inside the world
+fn check_in_world(world: &World, x: u32, y: u32) -> Result<(), String> {
+    let terrain = world.resource::<Terrain>();
+
+    if x >= terrain.width || y >= terrain.height {
+        return Err(format!(
+            "{}, {} is outside the world ({}x{})",
+            x, y, terrain.width, terrain.height
+        ));
+    }
+
+    Ok(())
+}
+
+fn tile_to_world(tm_transform: &Transform, x: u32, y: u32) -> Vec2 {
+    Vec2::new(
+        x as f32 * 8.0 + tm_transform.translation.x,
+        y as f32 * 8.0 + tm_transform.translation.y,
+    )
+}
+
+fn tile_names(_: usize) -> Vec<String> {
+    TileDescriptor::names().map(String::from).collect()
+}
+
+pub const HELP: ConsoleCommand = ConsoleCommand {
+    name: "help",
+    usage: "help",
+    run: help,
+    complete: None,
+};
+
+fn help(world: &mut World, _: &[&str]) -> Result<String, String> {
+    let usages: Vec<&str> = world
+        .resource::<ConsoleRegistry>()
+        .iter()
+        .map(|c| c.usage)
+        .collect();
+
+    Ok(usages.join("\n"))
+}
+
+pub const REGEN: ConsoleCommand = ConsoleCommand {
+    name: "regen",
+    usage: "regen [seed]",
+    run: regen,
+    complete: None,
+};
+
+fn regen(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut config = world.resource_mut::<WorldConfig>();
+
+    if let Some(seed) = args.first() {
+        config.seed = seed.to_string();
+    }
+
+    let output = format!("Regenerating world with seed {}", config.seed);
+    world.send_event(RegenerateWorld);
+
+    Ok(output)
+}
+
+pub const TP: ConsoleCommand = ConsoleCommand {
+    name: "tp",
+    usage: "tp <x> <y>",
+    run: tp,
+    complete: None,
+};
+
+fn tp(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let (x, y) = (arg::<u32>(args, 0, "x")?, arg::<u32>(args, 1, "y")?);
+    check_in_world(world, x, y)?;
+
+    let pos = tile_to_world(&front_transform(world)?, x, y);
+
+    // Stop following whatever was being followed
+    let targets: Vec<Entity> = world
+        .query_filtered::<Entity, With<CameraTarget>>()
+        .iter(world)
+        .collect();
+
+    for entity in targets {
+        world.entity_mut(entity).remove::<CameraTarget>();
+    }
+
+    let mut query = world.query_filtered::<&mut Transform, With<Camera2d>>();
+    let mut transform = query.single_mut(world);
+    transform.translation = pos.extend(transform.translation.z);
+
+    Ok(String::new())
+}
+
+pub const SPAWN: ConsoleCommand = ConsoleCommand {
+    name: "spawn",
+    usage: "spawn <character> <x> <y>",
+    run: spawn,
+    complete: Some(|i| match i {
+        0 => CharacterDesc::names().map(String::from).collect(),
+        _ => Vec::new(),
+    }),
+};
+
+fn spawn(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = args.first().ok_or("Missing argument: character")?;
+    let desc =
+        CharacterDesc::from_name(name).ok_or_else(|| format!("Unknown character: {}", name))?;
+    let (x, y) = (arg::<u32>(args, 1, "x")?, arg::<u32>(args, 2, "y")?);
+    check_in_world(world, x, y)?;
+
+    // Spawn slightly above the tile so the character doesn't get stuck
+    let pos = tile_to_world(&front_transform(world)?, x, y) + Vec2::new(0.0, 4.0);
+
+    let bundle = CharacterBundle::from_id(desc.id, pos, world.resource::<SpriteSheetHandles>());
+    world.spawn(bundle);
+
+    Ok(String::new())
+}
+
+pub const FILL: ConsoleCommand = ConsoleCommand {
+    name: "fill",
+    usage: "fill <x1> <y1> <x2> <y2> <tile>",
+    run: fill,
+    complete: Some(|i| match i {
+        4 => tile_names(i),
+        _ => Vec::new(),
+    }),
+};
+
+fn fill(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let (x1, y1) = (arg::<u32>(args, 0, "x1")?, arg::<u32>(args, 1, "y1")?);
+    let (x2, y2) = (arg::<u32>(args, 2, "x2")?, arg::<u32>(args, 3, "y2")?);
+
+    let name = args.get(4).ok_or("Missing argument: tile")?;
+    let desc = TileDescriptor::from_name(name).ok_or_else(|| format!("Unknown tile: {}", name))?;
+
+    // Filling would overlap the footprints
+    if desc.dimensions.is_some() {
+        return Err(format!(
+            "Can't fill with {}, it takes up more than one tile",
+            name
+        ));
+    }
+
+    let (id, layer) = (desc.id, desc.id.layer());
+
+    let mut state: SystemState<(
+        Commands,
+        ResMut<Terrain>,
+        Query<(Entity, &mut TileStorage, &TilemapLayer, &Transform)>,
+    )> = SystemState::new(world);
+
+    let (mut commands, mut terrain, mut tm_query) = state.get_mut(world);
+
+    let (tm_entity, mut tm_storage, _, tm_transform) = tm_query
+        .iter_mut()
+        .find(|(_, _, tm_layer, _)| tm_layer.0 == layer)
+        .ok_or("No tilemap for the tile's layer")?;
+
+    let mut count = 0;
+
+    for x in x1.min(x2)..=x1.max(x2).min(terrain.width - 1) {
+        for y in y1.min(y2)..=y1.max(y2).min(terrain.height - 1) {
+            let pos = TilePos::new(x, y);
+
+            terrain.remove_tile(
+                &mut commands,
+                &mut tm_storage,
+                layer,
+                pos,
+                ChangeCause::Edit,
+            );
+
+            if id == TileId::Empty {
+                continue;
+            }
+
+            let offset = terrain.autotile_offset(id, x, y);
+
+            terrain.insert_tile(
+                &mut commands,
+                &mut tm_storage,
+                tm_transform,
+                tm_entity,
+                layer,
+                pos,
+                Tile::new(id, offset),
+                ChangeCause::Edit,
+            );
+
+            count += 1;
+        }
+    }
+
+    state.apply(world);
+
+    Ok(format!("Placed {} tiles", count))
+}
+
+pub const PATH: ConsoleCommand = ConsoleCommand {
+    name: "path",
+    usage: "path <x1> <y1> <x2> <y2>",
+    run: path,
+    complete: None,
+};
+
+fn path(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let start = PathNode::new(arg(args, 0, "x1")?, arg(args, 1, "y1")?);
+    let goal = PathNode::new(arg(args, 2, "x2")?, arg(args, 3, "y2")?);
+    check_in_world(world, start.x, start.y)?;
+    check_in_world(world, goal.x, goal.y)?;
+
+    let tm_transform = front_transform(world)?;
+
+    let mut state: SystemState<(ResMut<PathState>, Res<Terrain>, ResMut<DebugLines>)> =
+        SystemState::new(world);
+
+    let (mut path_state, terrain, mut lines) = state.get_mut(world);
+
+    path_state.start = start;
+    path_state.goal = goal;
+    path_state.display_path(&terrain, &tm_transform, &mut lines);
+
+    match path_state.result {
+        Some((length, cost)) => Ok(format!("Path: {} tiles, cost {}", length, cost)),
+        None => Err("No path found".to_string()),
+    }
+}
+
+pub const SET: ConsoleCommand = ConsoleCommand {
+    name: "set",
+    usage: "set <setting> [value]",
+    run: set,
+    complete: Some(|i| match i {
+        0 => GenerationSettings::FIELDS
+            .iter()
+            .map(|f| f.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }),
+};
+
+// Changes a generation setting, or prints it if no value is given
+fn set(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let field = args.first().ok_or("Missing argument: setting")?;
+    let mut config = world.resource_mut::<WorldConfig>();
+
+    match args.get(1) {
+        Some(value) => {
+            config.settings.set_field(field, value)?;
+
+            Ok(format!("{} = {}, regen to apply", field, value))
+        }
+        None => {
+            let value = config
+                .settings
+                .get_field(field)
+                .ok_or_else(|| format!("Unknown setting: {}", field))?;
+
+            Ok(format!("{} = {}", field, value))
+        }
+    }
+}
+
+pub const PRESET: ConsoleCommand = ConsoleCommand {
+    name: "preset",
+    usage: "preset <name>",
+    run: preset,
+    complete: Some(|i| match i {
+        0 => GenerationSettings::presets(),
+        _ => Vec::new(),
+    }),
+};
+
+// Replaces the generation settings with a preset from assets/worldgen
+fn preset(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = args.first().ok_or("Missing argument: name")?;
+    let settings = GenerationSettings::load_preset(name)?;
+
+    world.resource_mut::<WorldConfig>().settings = settings;
+
+    Ok(format!("Loaded preset {}, regen to apply", name))
+}
+
+pub const SAVE: ConsoleCommand = ConsoleCommand {
+    name: "save",
+    usage: "save <path>",
+    run: save,
+    complete: None,
+};
+
+// Saves the world so it can be loaded with --load
+fn save(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let path = args.first().ok_or("Missing argument: path")?;
+    world.resource::<Terrain>().save(path)?;
+
+    Ok(format!("Saved world to {}", path))
+}
+
+pub const ORES: ConsoleCommand = ConsoleCommand {
+    name: "ores",
+    usage: "ores",
+    run: ores,
+    complete: None,
+};
+
+// Report how much of each ore the world has, to help balance generation
+fn ores(world: &mut World, _: &[&str]) -> Result<String, String> {
+    let stats: Vec<String> = world
+        .resource::<Terrain>()
+        .ore_stats()
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+    Ok(stats.join("\n"))
+}
+
+pub const BIND: ConsoleCommand = ConsoleCommand {
+    name: "bind",
+    usage: "bind <action>",
+    run: bind,
+    complete: Some(|i| match i {
+        0 => Action::names().collect(),
+        _ => Vec::new(),
+    }),
+};
+
+// Binds the next input pressed to an action, replacing its bindings
+fn bind(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = args.first().ok_or("Missing argument: action")?;
+    let action = Action::from_name(name).ok_or_else(|| format!("Unknown action: {}", name))?;
+
+    world.resource_mut::<Rebinding>().0 = Some(action);
+
+    // The console would take the key otherwise
+    world.resource_mut::<Console>().open = false;
+    world.resource_mut::<ActionState>().captured = false;
+
+    Ok(format!("Press an input to bind to {:?}", action))
+}
@@ -0,0 +1,346 @@
+// In-game developer console. Commands are stored in a registry
+// so that any module can add its own with add_console_command
+
+pub mod commands;
+
+use bevy::prelude::*;
+
+use self::commands::*;
+use crate::hud::HUD_FONT;
+use crate::input::{Action, ActionState};
+
+// No. of output lines kept and displayed
+const HISTORY_LENGTH: usize = 12;
+const CONSOLE_FONT_SIZE: f32 = 14.0;
+
+// Runs a command with its arguments, returning the text to print
+pub type CommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+// Returns the possible values of an argument, used for autocompletion
+pub type CompleteFn = fn(usize) -> Vec<String>;
+
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub run: CommandFn,
+    pub complete: Option<CompleteFn>,
+}
+
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConsoleSystem {
+    // Typing into the prompt while the console is open
+    Input,
+
+    // Commands entered this frame are run once input has been read
+    Run,
+    Display,
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_startup_system(setup_console)
+            .add_system(update_console_input.label(ConsoleSystem::Input))
+            .add_system(
+                run_console_commands
+                    .label(ConsoleSystem::Run)
+                    .after(ConsoleSystem::Input),
+            )
+            .add_system(
+                update_console_display
+                    .label(ConsoleSystem::Display)
+                    .after(ConsoleSystem::Run),
+            )
+            .add_console_command(HELP)
+            .add_console_command(REGEN)
+            .add_console_command(TP)
+            .add_console_command(SPAWN)
+            .add_console_command(FILL)
+            .add_console_command(PATH)
+            .add_console_command(SET)
+            .add_console_command(PRESET)
+            .add_console_command(SAVE)
+            .add_console_command(ORES)
+            .add_console_command(BIND);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleRegistry {
+    commands: Vec<ConsoleCommand>,
+}
+
+impl ConsoleRegistry {
+    // Adding a command with an existing name replaces it
+    pub fn register(&mut self, command: ConsoleCommand) {
+        self.commands.retain(|c| c.name != command.name);
+        self.commands.push(command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.commands.iter().find(|c| c.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ConsoleCommand> {
+        self.commands.iter()
+    }
+}
+
+pub trait ConsoleAppExt {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleRegistry::default)
+            .register(command);
+
+        self
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+
+    // Output shown above the input line
+    pub history: Vec<String>,
+
+    // Lines that have been entered but not yet run
+    pending: Vec<String>,
+
+    // Previously entered lines, most recent last
+    entered: Vec<String>,
+    recall: usize,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.history.push(line.into());
+
+        if self.history.len() > HISTORY_LENGTH {
+            self.history.remove(0);
+        }
+    }
+
+    // Queue a line to be run at the end of the frame
+    pub fn submit(&mut self, line: impl Into<String>) {
+        let line = line.into();
+
+        self.print(format!("> {}", line));
+        self.entered.push(line.clone());
+        self.recall = self.entered.len();
+        self.pending.push(line);
+    }
+}
+
+#[derive(Component)]
+pub struct ConsoleNode;
+
+#[derive(Component)]
+pub struct ConsoleText;
+
+pub fn setup_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.0),
+                    bottom: Val::Px(0.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+            ..Default::default()
+        })
+        .insert(ConsoleNode)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load(HUD_FONT),
+                        font_size: CONSOLE_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(ConsoleText);
+        });
+}
+
+// Handles opening, closing and typing into the console
+pub fn update_console_input(
+    mut console: ResMut<Console>,
+    mut actions: ResMut<ActionState>,
+    mut chars: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    registry: Res<ConsoleRegistry>,
+) {
+    if !console.open {
+        if actions.just_pressed(Action::ToggleConsole) {
+            console.open = true;
+            actions.captured = true;
+
+            // Don't type the key that opened the console
+            chars.clear();
+        }
+
+        return;
+    }
+
+    // Escape always closes the console, even if ToggleConsole was rebound
+    if keys.just_pressed(KeyCode::Escape) || actions.just_pressed(Action::ToggleConsole) {
+        console.open = false;
+        actions.captured = false;
+        return;
+    }
+
+    for c in chars.iter() {
+        if !c.char.is_control() {
+            console.input.push(c.char);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+
+    if keys.just_pressed(KeyCode::Return) && !console.input.trim().is_empty() {
+        let line = std::mem::take(&mut console.input);
+        console.submit(line);
+    }
+
+    if keys.just_pressed(KeyCode::Tab) {
+        autocomplete(&mut console, &registry);
+    }
+
+    // Recall previously entered lines
+    if keys.just_pressed(KeyCode::Up) && console.recall > 0 {
+        console.recall -= 1;
+        console.input = console.entered[console.recall].clone();
+    }
+
+    if keys.just_pressed(KeyCode::Down) && console.recall < console.entered.len() {
+        console.recall += 1;
+        console.input = console
+            .entered
+            .get(console.recall)
+            .cloned()
+            .unwrap_or_default();
+    }
+}
+
+// Complete the last word of the input
+fn autocomplete(console: &mut Console, registry: &ConsoleRegistry) {
+    let line = console.input.clone();
+    let words: Vec<&str> = line.split(' ').collect();
+    let (last, args) = words.split_last().expect("Split always returns a word");
+
+    let options: Vec<String> = if args.is_empty() {
+        registry.iter().map(|c| c.name.to_string()).collect()
+    } else {
+        match registry.get(args[0]).and_then(|c| c.complete) {
+            Some(complete) => complete(args.len() - 1),
+            None => Vec::new(),
+        }
+    };
+
+    let matches: Vec<&String> = options.iter().filter(|o| o.starts_with(last)).collect();
+
+    if matches.is_empty() {
+        return;
+    }
+
+    // Extend the word as far as all matches agree
+    let mut common = matches[0].clone();
+    for m in &matches[1..] {
+        while !m.starts_with(common.as_str()) {
+            common.pop();
+        }
+    }
+
+    if matches.len() > 1 {
+        let list: Vec<&str> = matches.iter().map(|m| m.as_str()).collect();
+        console.print(list.join(" "));
+    } else {
+        common.push(' ');
+    }
+
+    let mut completed = args.join(" ");
+    if !completed.is_empty() {
+        completed.push(' ');
+    }
+
+    completed.push_str(&common);
+    console.input = completed;
+}
+
+// Run every line entered this frame. This is exclusive so commands can access the whole world
+pub fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+
+    for line in pending {
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        if args.is_empty() {
+            continue;
+        }
+
+        // Copy the command out so it can borrow the world, including the registry
+        let command = world
+            .resource::<ConsoleRegistry>()
+            .get(args[0])
+            .map(|c| (c.run, c.usage));
+
+        let result = match command {
+            Some((run, usage)) => {
+                run(world, &args[1..]).map_err(|e| format!("{}\nUsage: {}", e, usage))
+            }
+            None => Err(format!("Unknown command: {}, try help", args[0])),
+        };
+
+        let mut console = world.resource_mut::<Console>();
+
+        match result {
+            Ok(output) if output.is_empty() => (),
+            Ok(output) => output.lines().for_each(|l| console.print(l)),
+            Err(e) => format!("Error: {}", e)
+                .lines()
+                .for_each(|l| console.print(l)),
+        }
+    }
+}
+
+pub fn update_console_display(
+    console: Res<Console>,
+    mut node_query: Query<&mut Style, With<ConsoleNode>>,
+    mut text_query: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+
+    for mut style in node_query.iter_mut() {
+        style.display = if console.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    for mut text in text_query.iter_mut() {
+        let mut value = console.history.join("\n");
+        value += &format!("\n> {}_", console.input);
+
+        text.sections[0].value = value;
+    }
+}
@@ -0,0 +1,227 @@
+// World generation editor. Exposes the seed and every generation
+// setting so they can be tuned, then regenerates the world in place
+
+use bevy::prelude::*;
+
+use crate::hud::HUD_FONT;
+use crate::input::{Action, ActionState};
+use crate::terrain::bevy_connect::{RegenerateWorld, TerrainSystem, WorldConfig};
+use crate::terrain::settings::GenerationSettings;
+
+const EDITOR_FONT_SIZE: f32 = 12.0;
+
+const BUTTON_COLOUR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVER_COLOUR: Color = Color::rgb(0.4, 0.4, 0.4);
+
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EditorSystem {
+    Input,
+    Display,
+}
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_editor)
+            // Regenerate requests are picked up on the same frame
+            .add_system_set(
+                SystemSet::new()
+                    .label(EditorSystem::Input)
+                    .before(TerrainSystem::Generation)
+                    .with_system(toggle_editor)
+                    .with_system(update_editor_buttons),
+            )
+            .add_system(
+                update_editor_text
+                    .label(EditorSystem::Display)
+                    .after(EditorSystem::Input),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct EditorPanel;
+
+// Text displaying the value of a setting
+#[derive(Component)]
+pub struct SettingText(pub &'static str);
+
+#[derive(Component)]
+pub struct SeedText;
+
+#[derive(Component, Clone, Copy)]
+pub enum EditorButton {
+    // Nudge a setting by a number of steps
+    Nudge(&'static str, i32),
+    RandomSeed,
+    Regenerate,
+}
+
+fn text(asset_server: &AssetServer, value: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font: asset_server.load(HUD_FONT),
+            font_size: EDITOR_FONT_SIZE,
+            color: Color::WHITE,
+        },
+    )
+}
+
+fn row_style() -> Style {
+    Style {
+        flex_direction: FlexDirection::Row,
+        align_items: AlignItems::Center,
+        ..Default::default()
+    }
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    label: &str,
+    button: EditorButton,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                margin: UiRect::horizontal(Val::Px(2.0)),
+                padding: UiRect::horizontal(Val::Px(4.0)),
+                ..Default::default()
+            },
+            background_color: BUTTON_COLOUR.into(),
+            ..Default::default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn(text(asset_server, label));
+        });
+}
+
+pub fn setup_editor(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(90.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(6.0)),
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+            ..Default::default()
+        })
+        // Lets the panel block clicks from reaching the world
+        .insert(Interaction::default())
+        .insert(EditorPanel)
+        .with_children(|parent| {
+            // Seed
+            parent
+                .spawn(NodeBundle {
+                    style: row_style(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(text(&asset_server, "seed: ")).insert(SeedText);
+                    spawn_button(parent, &asset_server, "random", EditorButton::RandomSeed);
+                });
+
+            // One row for each setting
+            for &field in GenerationSettings::FIELDS {
+                parent
+                    .spawn(NodeBundle {
+                        style: row_style(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        spawn_button(parent, &asset_server, "-", EditorButton::Nudge(field, -1));
+                        spawn_button(parent, &asset_server, "+", EditorButton::Nudge(field, 1));
+                        parent
+                            .spawn(text(&asset_server, field))
+                            .insert(SettingText(field));
+                    });
+            }
+
+            spawn_button(
+                parent,
+                &asset_server,
+                "regenerate",
+                EditorButton::Regenerate,
+            );
+        });
+}
+
+pub fn toggle_editor(actions: Res<ActionState>, mut query: Query<&mut Style, With<EditorPanel>>) {
+    if !actions.just_pressed(Action::ToggleEditor) {
+        return;
+    }
+
+    for mut style in query.iter_mut() {
+        style.display = match style.display {
+            Display::None => Display::Flex,
+            Display::Flex => Display::None,
+        };
+    }
+}
+
+pub fn update_editor_buttons(
+    keys: Res<Input<KeyCode>>,
+    mut config: ResMut<WorldConfig>,
+    mut events: EventWriter<RegenerateWorld>,
+    mut query: Query<(&Interaction, &EditorButton, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, button, mut colour) in query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => (),
+            Interaction::Hovered => {
+                *colour = BUTTON_HOVER_COLOUR.into();
+                continue;
+            }
+            Interaction::None => {
+                *colour = BUTTON_COLOUR.into();
+                continue;
+            }
+        }
+
+        match *button {
+            EditorButton::Nudge(field, steps) => {
+                // Shift makes bigger steps
+                let steps = if keys.pressed(KeyCode::LShift) {
+                    steps * 10
+                } else {
+                    steps
+                };
+
+                config.settings.nudge_field(field, steps);
+            }
+            EditorButton::RandomSeed => config.seed = rand::random::<u32>().to_string(),
+            EditorButton::Regenerate => events.send(RegenerateWorld),
+        }
+    }
+}
+
+// Show the current values whenever they change
+pub fn update_editor_text(
+    config: Res<WorldConfig>,
+    mut setting_query: Query<(&mut Text, &SettingText), Without<SeedText>>,
+    mut seed_query: Query<&mut Text, With<SeedText>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    for (mut text, setting) in setting_query.iter_mut() {
+        let value = config.settings.get_field(setting.0).unwrap_or_default();
+        text.sections[0].value = format!("{}: {}", setting.0, value);
+    }
+
+    for mut text in seed_query.iter_mut() {
+        text.sections[0].value = format!("seed: {}", config.seed);
+    }
+}
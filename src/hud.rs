@@ -0,0 +1,139 @@
+// Status display showing the current command mode, the tile
+// under the cursor, the active brush and pathfinding results
+
+use bevy::math::Vec4Swizzles;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_prototype_debug_lines::DebugLines;
+
+use crate::camera::CursorPos;
+use crate::player::{Brush, CommandMode, PathState, PlayerSystem};
+use crate::state::AppState;
+use crate::terrain::bevy_connect::TilemapLayer;
+use crate::terrain::*;
+use crate::tile::TileId;
+
+pub const HUD_FONT: &str = "fonts/DejaVuSansMono.ttf";
+pub const HUD_FONT_SIZE: f32 = 16.0;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_hud)
+            .add_system(update_hud.after(PlayerSystem::Input));
+    }
+}
+
+#[derive(Component)]
+pub struct HudText;
+
+pub fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(HUD_FONT),
+                    font_size: HUD_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        )
+        .insert(HudText);
+}
+
+pub fn update_hud(
+    mode: Res<CommandMode>,
+    brush: Res<Brush>,
+    path_state: Res<PathState>,
+    cursor: Res<CursorPos>,
+    terrain: Res<Terrain>,
+    mut lines: ResMut<DebugLines>,
+    tm_query: Query<(&TilemapSize, &TilemapGridSize, &TilemapLayer, &Transform)>,
+    state: Res<State<AppState>>,
+    mut text_query: Query<&mut Text, With<HudText>>,
+) {
+    let mut status = format!("Mode: {:?}\n", *mode);
+
+    if *state.current() == AppState::Paused {
+        status = format!("PAUSED\n{}", status);
+    }
+
+    // Tile under the cursor
+    if let Some((tm_size, tm_grid_size, _, tm_transform)) =
+        tm_query.iter().find(|(_, _, layer, _)| layer.0 == FRONT)
+    {
+        let world_pos =
+            (tm_transform.compute_matrix().inverse() * Vec4::from((cursor.0, 0.0, 1.0))).xy();
+
+        if let Some(tile_pos) =
+            TilePos::from_world_pos(&world_pos, tm_size, tm_grid_size, &TilemapType::Square)
+        {
+            // Report the front-most tile
+            let layer = (0..TOTAL_LAYERS)
+                .find(|l| terrain.layers[*l][(tile_pos.x, tile_pos.y)].id != TileId::Empty)
+                .unwrap_or(BACK);
+
+            status += &format!(
+                "Cursor: ({}, {}) {:?} [{}]\n",
+                tile_pos.x,
+                tile_pos.y,
+                terrain.layers[layer][(tile_pos.x, tile_pos.y)].id,
+                LAYER_NAMES[layer],
+            );
+
+            // Outline the targeted tile, or the whole multi-tile it belongs to
+            let (anchor, size) = match terrain
+                .multi_tile_at(tile_pos.x, tile_pos.y)
+                .filter(|object| object.id.layer() == layer)
+            {
+                Some(object) => (object.anchor, object.size),
+                None => ((tile_pos.x, tile_pos.y), (1, 1)),
+            };
+
+            let bottom_left = Vec2::new(
+                anchor.0 as f32 * 8.0 + tm_transform.translation.x - 4.0,
+                anchor.1 as f32 * 8.0 + tm_transform.translation.y - 4.0,
+            );
+
+            let (width, height) = (size.0 as f32 * 8.0, size.1 as f32 * 8.0);
+
+            let corners = [
+                bottom_left,
+                bottom_left + Vec2::new(width, 0.0),
+                bottom_left + Vec2::new(width, height),
+                bottom_left + Vec2::new(0.0, height),
+            ];
+
+            for i in 0..corners.len() {
+                let (a, b) = (corners[i], corners[(i + 1) % corners.len()]);
+                lines.line_colored(a.extend(0.0), b.extend(0.0), 0.0, Color::YELLOW);
+            }
+        } else {
+            status += "Cursor: outside the world\n";
+        }
+    }
+
+    status += &format!("Brush: {:?} [{}]\n", brush.tile, LAYER_NAMES[brush.layer]);
+
+    if let CommandMode::PathFinding = *mode {
+        match path_state.result {
+            Some((length, cost)) => status += &format!("Path: {} tiles, cost {}\n", length, cost),
+            None => status += "Path: none\n",
+        }
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = status.clone();
+    }
+}
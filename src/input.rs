@@ -0,0 +1,383 @@
+// Input action map. Systems query abstract actions rather than
+// raw keys so that bindings can be changed without touching them.
+// Bindings are loaded from a config file and can be rebound at runtime
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem::variant_count;
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionSystem {
+    // Binding the next input pressed, before it can trigger anything
+    Rebind,
+
+    // Translating input into actions, before any other system reads them
+    Update,
+}
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load(InputMap::PATH))
+            .init_resource::<ActionState>()
+            .init_resource::<Rebinding>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                rebind_action.label(ActionSystem::Rebind).after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_action_state
+                    .label(ActionSystem::Update)
+                    .after(ActionSystem::Rebind),
+            );
+    }
+}
+
+// Everything the player can do with an input device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ZoomIn,
+    ZoomOut,
+    Follow,
+    ToggleMap,
+    ToggleConsole,
+    ToggleEditor,
+    Pause,
+
+    ModeTerrain,
+    ModePathFinding,
+    ModePlaceEntity,
+
+    // Left and right click by default
+    Primary,
+    Secondary,
+}
+
+impl Action {
+    // Sized by the number of variants so a missing action fails to compile
+    pub const ALL: [Self; variant_count::<Action>()] = [
+        Self::PanUp,
+        Self::PanDown,
+        Self::PanLeft,
+        Self::PanRight,
+        Self::ZoomIn,
+        Self::ZoomOut,
+        Self::Follow,
+        Self::ToggleMap,
+        Self::ToggleConsole,
+        Self::ToggleEditor,
+        Self::Pause,
+        Self::ModeTerrain,
+        Self::ModePathFinding,
+        Self::ModePlaceEntity,
+        Self::Primary,
+        Self::Secondary,
+    ];
+
+    // Actions are named the same as in the bindings file
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| format!("{:?}", a) == name)
+    }
+
+    pub fn names() -> impl Iterator<Item = String> {
+        Self::ALL.iter().map(|a| format!("{:?}", a))
+    }
+}
+
+// A single physical input that can trigger an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub const PATH: &str = "assets/config/bindings.ron";
+
+    // Load bindings from a file, falling back to the defaults if it can't be read
+    pub fn load(path: &str) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!(
+                    "Could not read bindings from {}: {}, using defaults",
+                    path, e
+                );
+                return Self::default();
+            }
+        };
+
+        match ron::from_str(&contents) {
+            Ok(map) => map,
+            Err(e) => {
+                warn!("Invalid bindings in {}: {}, using defaults", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("Failed to serialize bindings");
+
+        if let Err(e) = fs::write(path, contents) {
+            warn!("Could not save bindings to {}: {}", path, e);
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    // Bind an input to an action. An input can only trigger
+    // one action, so it is removed from any others first
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|b| *b != binding);
+        }
+
+        self.bindings.entry(action).or_default().push(binding);
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = HashMap::from([
+            (
+                Action::PanUp,
+                vec![
+                    Binding::Key(KeyCode::W),
+                    Binding::Gamepad(GamepadButtonType::DPadUp),
+                ],
+            ),
+            (
+                Action::PanDown,
+                vec![
+                    Binding::Key(KeyCode::S),
+                    Binding::Gamepad(GamepadButtonType::DPadDown),
+                ],
+            ),
+            (
+                Action::PanLeft,
+                vec![
+                    Binding::Key(KeyCode::A),
+                    Binding::Gamepad(GamepadButtonType::DPadLeft),
+                ],
+            ),
+            (
+                Action::PanRight,
+                vec![
+                    Binding::Key(KeyCode::D),
+                    Binding::Gamepad(GamepadButtonType::DPadRight),
+                ],
+            ),
+            (Action::ZoomIn, vec![Binding::Key(KeyCode::Equals)]),
+            (Action::ZoomOut, vec![Binding::Key(KeyCode::Minus)]),
+            (Action::Follow, vec![Binding::Key(KeyCode::F)]),
+            (Action::ToggleMap, vec![Binding::Key(KeyCode::M)]),
+            (Action::ToggleConsole, vec![Binding::Key(KeyCode::Grave)]),
+            (Action::ToggleEditor, vec![Binding::Key(KeyCode::F1)]),
+            (
+                Action::Pause,
+                vec![
+                    Binding::Key(KeyCode::Space),
+                    Binding::Gamepad(GamepadButtonType::Start),
+                ],
+            ),
+            (Action::ModeTerrain, vec![Binding::Key(KeyCode::T)]),
+            (Action::ModePathFinding, vec![Binding::Key(KeyCode::P)]),
+            (Action::ModePlaceEntity, vec![Binding::Key(KeyCode::E)]),
+            (
+                Action::Primary,
+                vec![
+                    Binding::Mouse(MouseButton::Left),
+                    Binding::Gamepad(GamepadButtonType::South),
+                ],
+            ),
+            (
+                Action::Secondary,
+                vec![
+                    Binding::Mouse(MouseButton::Right),
+                    Binding::Gamepad(GamepadButtonType::East),
+                ],
+            ),
+        ]);
+
+        Self { bindings }
+    }
+}
+
+// The state of every action this frame
+#[derive(Resource, Default)]
+pub struct ActionState {
+    // Set while a text field is using the keyboard, key bindings are ignored
+    // apart from ToggleConsole so the console can always be closed
+    pub captured: bool,
+
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+}
+
+// Raw input state for every supported device
+struct Devices<'a> {
+    keys: &'a Input<KeyCode>,
+    mouse: &'a Input<MouseButton>,
+    gamepads: &'a Gamepads,
+    buttons: &'a Input<GamepadButton>,
+}
+
+impl<'a> Devices<'a> {
+    // Checks a binding against each device using the given Input method
+    fn check<F, G, H>(&self, binding: &Binding, key: F, mouse: G, button: H) -> bool
+    where
+        F: Fn(&Input<KeyCode>, KeyCode) -> bool,
+        G: Fn(&Input<MouseButton>, MouseButton) -> bool,
+        H: Fn(&Input<GamepadButton>, GamepadButton) -> bool,
+    {
+        match *binding {
+            Binding::Key(k) => key(self.keys, k),
+            Binding::Mouse(m) => mouse(self.mouse, m),
+            Binding::Gamepad(b) => self
+                .gamepads
+                .iter()
+                .any(|g| button(self.buttons, GamepadButton::new(g, b))),
+        }
+    }
+}
+
+// Translate raw input into actions
+pub fn update_action_state(
+    map: Res<InputMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<ActionState>,
+) {
+    let devices = Devices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepads: &gamepads,
+        buttons: &buttons,
+    };
+
+    state.pressed.clear();
+    state.just_pressed.clear();
+    state.just_released.clear();
+
+    for (action, bindings) in &map.bindings {
+        for binding in bindings {
+            if state.captured
+                && *action != Action::ToggleConsole
+                && matches!(binding, Binding::Key(_))
+            {
+                continue;
+            }
+
+            if devices.check(
+                binding,
+                |i, k| i.pressed(k),
+                |i, m| i.pressed(m),
+                |i, b| i.pressed(b),
+            ) {
+                state.pressed.insert(*action);
+            }
+
+            if devices.check(
+                binding,
+                |i, k| i.just_pressed(k),
+                |i, m| i.just_pressed(m),
+                |i, b| i.just_pressed(b),
+            ) {
+                state.just_pressed.insert(*action);
+            }
+
+            if devices.check(
+                binding,
+                |i, k| i.just_released(k),
+                |i, m| i.just_released(m),
+                |i, b| i.just_released(b),
+            ) {
+                state.just_released.insert(*action);
+            }
+        }
+    }
+}
+
+// When set, the next input pressed is bound to this action
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<Action>);
+
+// Runs before update_action_state, the input that is bound is consumed
+// so it doesn't also trigger the action it was bound to before
+pub fn rebind_action(
+    mut map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut mouse: ResMut<Input<MouseButton>>,
+    mut buttons: ResMut<Input<GamepadButton>>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+
+    let key = keys.get_just_pressed().next().copied();
+    let click = mouse.get_just_pressed().next().copied();
+    let button = buttons.get_just_pressed().next().copied();
+
+    let binding = if let Some(k) = key {
+        keys.reset(k);
+        Binding::Key(k)
+    } else if let Some(m) = click {
+        mouse.reset(m);
+        Binding::Mouse(m)
+    } else if let Some(b) = button {
+        buttons.reset(b);
+        Binding::Gamepad(b.button_type)
+    } else {
+        return;
+    };
+
+    // Rebinding replaces all previous bindings
+    map.clear(action);
+    map.bind(action, binding);
+    map.save(InputMap::PATH);
+
+    info!("Bound {:?} to {:?}", action, binding);
+    rebinding.0 = None;
+}
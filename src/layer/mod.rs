@@ -0,0 +1,853 @@
+pub mod storage;
+
+use std::ops::{Index, IndexMut};
+
+use serde::{Deserialize, Serialize};
+
+pub use self::storage::*;
+
+// Which surrounding cells count as neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    Moore,      // All 8 surrounding cells
+    VonNeumann, // Only the 4 cells sharing an edge
+}
+
+impl Neighbourhood {
+    // Offsets of each neighbour, Moore is ordered to match Surrounds
+    pub fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Self::Moore => &[
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+                (-1, 0),
+                (1, 0),
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+            ],
+            Self::VonNeumann => &[(0, 1), (-1, 0), (1, 0), (0, -1)],
+        }
+    }
+}
+
+// Generic layer interface
+// Elements are indexed column by column, in dense or sparse storage
+#[derive(Serialize, Deserialize)]
+#[serde(
+    into = "LayerData<T>",
+    try_from = "LayerData<T>",
+    bound(
+        serialize = "T: Cell + Serialize",
+        deserialize = "T: Cell + Deserialize<'de>"
+    )
+)]
+pub struct Layer<T: Cell> {
+    pub width: u32,
+    pub height: u32,
+
+    storage: Storage<T>,
+}
+
+// How layers are saved. Runs of equal elements are stored as one to keep saves small
+#[derive(Serialize, Deserialize)]
+pub struct LayerData<T> {
+    width: u32,
+    height: u32,
+    storage: StorageKind,
+    runs: Vec<(u32, T)>,
+}
+
+impl<T: Cell> From<Layer<T>> for LayerData<T> {
+    fn from(layer: Layer<T>) -> Self {
+        let mut runs: Vec<(u32, T)> = Vec::new();
+
+        for (_, element) in layer.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if last == element => *count += 1,
+                _ => runs.push((1, element.clone())),
+            }
+        }
+
+        Self {
+            width: layer.width,
+            height: layer.height,
+            storage: layer.storage_kind(),
+            runs,
+        }
+    }
+}
+
+impl<T: Cell> TryFrom<LayerData<T>> for Layer<T> {
+    type Error = String;
+
+    fn try_from(data: LayerData<T>) -> Result<Self, Self::Error> {
+        let mut layer = Layer::with_storage(data.width, data.height, data.storage);
+
+        let count: usize = data.runs.iter().map(|(count, _)| *count as usize).sum();
+
+        if count != layer.len() {
+            return Err(format!(
+                "{}x{} layer has {} elements, expected {}",
+                data.width,
+                data.height,
+                count,
+                layer.len()
+            ));
+        }
+
+        // Sparse layers don't store their default, so the most common
+        // element is used as it leaves the fewest elements stored
+        if data.storage == StorageKind::Sparse
+            && let Some(common) = most_common(&data.runs)
+        {
+            layer.fill(common);
+        }
+
+        let mut index = 0;
+
+        for (count, element) in data.runs {
+            for _ in 0..count {
+                layer.set_index(index, element.clone());
+                index += 1;
+            }
+        }
+
+        Ok(layer)
+    }
+}
+
+// The element covering the most cells across all of the runs
+fn most_common<T: Cell>(runs: &[(u32, T)]) -> Option<T> {
+    let mut totals: Vec<(u32, &T)> = Vec::new();
+
+    for (count, element) in runs {
+        match totals.iter_mut().find(|(_, e)| *e == element) {
+            Some((total, _)) => *total += count,
+            None => totals.push((*count, element)),
+        }
+    }
+
+    totals
+        .into_iter()
+        .max_by_key(|(total, _)| *total)
+        .map(|(_, element)| element.clone())
+}
+
+impl<T: Cell> Layer<T> {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_storage(width, height, StorageKind::Dense)
+    }
+
+    pub fn with_storage(width: u32, height: u32, kind: StorageKind) -> Self {
+        Self {
+            width,
+            height,
+            storage: Storage::new(kind, (width * height) as usize),
+        }
+    }
+
+    pub fn storage_kind(&self) -> StorageKind {
+        self.storage.kind()
+    }
+
+    // Free memory used while the layer was being modified
+    pub fn compact(&mut self) {
+        self.storage.compact();
+    }
+
+    pub fn fill(&mut self, value: T) {
+        self.storage.fill(value);
+    }
+
+    // Parts of the rectangle outside the layer are ignored
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, value: T) {
+        let (xs, ys) = self.clip(x, y, width, height);
+
+        for x in xs {
+            for y in ys.clone() {
+                self.set((x, y), value.clone());
+            }
+        }
+    }
+
+    // Sparse layers only store the element if it isn't the default,
+    // unlike writing through get_mut or indexing
+    pub fn set(&mut self, pos: (u32, u32), value: T) {
+        let index = self.index_of(pos.0, pos.1);
+        self.set_index(index, value);
+    }
+
+    fn set_index(&mut self, index: usize, value: T) {
+        self.storage.set(index, value);
+    }
+
+    fn len(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    fn index_of(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "({}, {}) is outside of a {}x{} layer",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+
+        (x * self.height + y) as usize
+    }
+
+    fn position_of(&self, index: usize) -> (u32, u32) {
+        (index as u32 / self.height, index as u32 % self.height)
+    }
+
+    pub fn get(&self, x: isize, y: isize) -> Option<&T> {
+        if x >= 0 && x < self.width as isize && y >= 0 && y < self.height as isize {
+            Some(&self[(x as u32, y as u32)])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, x: isize, y: isize) -> Option<&mut T> {
+        if x >= 0 && x < self.width as isize && y >= 0 && y < self.height as isize {
+            Some(&mut self[(x as u32, y as u32)])
+        } else {
+            None
+        }
+    }
+
+    // Every element along with its position, column by column
+    pub fn iter(&self) -> impl Iterator<Item = ((u32, u32), &T)> {
+        (0..self.len()).map(|i| (self.position_of(i), self.storage.get(i)))
+    }
+
+    // Every stored element along with its position. Dense layers store every
+    // element, sparse layers only those that differ from their default, in
+    // no particular order
+    pub fn iter_stored_mut(&mut self) -> impl Iterator<Item = ((u32, u32), &mut T)> {
+        let height = self.height;
+
+        self.storage
+            .iter_stored_mut()
+            .map(move |(i, element)| ((i as u32 / height, i as u32 % height), element))
+    }
+
+    // Column x from bottom to top
+    pub fn column(&self, x: u32) -> impl Iterator<Item = &T> {
+        (0..self.height).map(move |y| &self[(x, y)])
+    }
+
+    // Row y from left to right
+    pub fn row(&self, y: u32) -> impl Iterator<Item = &T> {
+        (0..self.width).map(move |x| &self[(x, y)])
+    }
+
+    // Each column of the layer from left to right, used to modify columns in parallel
+    // Only dense layers are stored contiguously, so this panics for sparse layers
+    pub fn columns_mut(&mut self) -> impl Iterator<Item = &mut [T]> {
+        let height = self.height.max(1) as usize;
+
+        self.storage
+            .as_mut_slice()
+            .expect("Only dense layers can be split into columns")
+            .chunks_mut(height)
+    }
+
+    // Every element in the rectangle with its bottom left corner at (x, y)
+    // Parts of the rectangle outside the layer are ignored
+    pub fn region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> impl Iterator<Item = ((u32, u32), &T)> {
+        let (xs, ys) = self.clip(x, y, width, height);
+
+        xs.flat_map(move |x| ys.clone().map(move |y| ((x, y), &self[(x, y)])))
+    }
+
+    // Like iter_stored_mut, but only the elements inside the rectangle
+    pub fn region_stored_mut(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Box<dyn Iterator<Item = ((u32, u32), &mut T)> + '_> {
+        let (xs, ys) = self.clip(x, y, width, height);
+
+        if self.storage.as_slice().is_none() {
+            return Box::new(
+                self.iter_stored_mut()
+                    .filter(move |((x, y), _)| xs.contains(x) && ys.contains(y)),
+            );
+        }
+
+        // Dense layers can skip straight to the rectangle
+        Box::new(
+            self.columns_mut()
+                .enumerate()
+                .skip(xs.start as usize)
+                .take(xs.len())
+                .flat_map(move |(x, column)| {
+                    column[ys.start as usize..ys.end as usize]
+                        .iter_mut()
+                        .enumerate()
+                        .map(move |(i, element)| ((x as u32, ys.start + i as u32), element))
+                }),
+        )
+    }
+
+    // The columns and rows of a rectangle that are inside the layer
+    fn clip(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        (
+            x.min(self.width)..x.saturating_add(width).min(self.width),
+            y.min(self.height)..y.saturating_add(height).min(self.height),
+        )
+    }
+
+    // Neighbours of (x, y) that are inside the layer
+    pub fn neighbours(
+        &self,
+        x: u32,
+        y: u32,
+        neighbourhood: Neighbourhood,
+    ) -> impl Iterator<Item = ((u32, u32), &T)> {
+        neighbourhood.offsets().iter().filter_map(move |(dx, dy)| {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            self.get(nx, ny)
+                .map(|element| ((nx as u32, ny as u32), element))
+        })
+    }
+
+    // Every position connected to (x, y) through matching elements
+    // Empty if the element at (x, y) doesn't match
+    pub fn flood_fill(
+        &self,
+        x: u32,
+        y: u32,
+        neighbourhood: Neighbourhood,
+        matches: impl Fn(&T) -> bool,
+    ) -> Vec<(u32, u32)> {
+        if !matches(&self[(x, y)]) {
+            return Vec::new();
+        }
+
+        let mut visited = vec![false; self.len()];
+        visited[self.index_of(x, y)] = true;
+
+        let mut region = Vec::new();
+        let mut stack = vec![(x, y)];
+
+        while let Some((x, y)) = stack.pop() {
+            region.push((x, y));
+
+            for (pos, element) in self.neighbours(x, y, neighbourhood) {
+                let i = self.index_of(pos.0, pos.1);
+
+                if !visited[i] && matches(element) {
+                    visited[i] = true;
+                    stack.push(pos);
+                }
+            }
+        }
+
+        region
+    }
+
+    // Label each group of connected matching elements
+    // Returns the label of every element and the size of each group
+    pub fn label(
+        &self,
+        neighbourhood: Neighbourhood,
+        matches: impl Fn(&T) -> bool,
+    ) -> (Layer<Option<usize>>, Vec<usize>) {
+        let mut labels: Layer<Option<usize>> = Layer::new(self.width, self.height);
+        let mut sizes = Vec::new();
+
+        for (pos, element) in self.iter() {
+            if labels[pos].is_some() || !matches(element) {
+                continue;
+            }
+
+            let label = sizes.len();
+            let mut size = 0;
+            let mut stack = vec![pos];
+            labels[pos] = Some(label);
+
+            while let Some((x, y)) = stack.pop() {
+                size += 1;
+
+                for (pos, element) in self.neighbours(x, y, neighbourhood) {
+                    if labels[pos].is_none() && matches(element) {
+                        labels[pos] = Some(label);
+                        stack.push(pos);
+                    }
+                }
+            }
+
+            sizes.push(size);
+        }
+
+        (labels, sizes)
+    }
+
+    // Walk the cells on the line from one point to another, returning the first that matches
+    // Points are in cells, with cell (x, y) covering x..x + 1 and y..y + 1
+    pub fn raycast(
+        &self,
+        from: (f32, f32),
+        to: (f32, f32),
+        matches: impl Fn(&T) -> bool,
+    ) -> Option<(u32, u32)> {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+
+        let (mut x, mut y) = (from.0.floor() as isize, from.1.floor() as isize);
+        let end = (to.0.floor() as isize, to.1.floor() as isize);
+
+        let (step_x, step_y) = (dx.signum() as isize, dy.signum() as isize);
+
+        // How far along the line, as a fraction of its length, the next
+        // vertical and horizontal cell edges are and the gap between them
+        let edge = |start: f32, cell: isize, d: f32| {
+            if d > 0.0 {
+                (cell as f32 + 1.0 - start) / d
+            } else if d < 0.0 {
+                (cell as f32 - start) / d
+            } else {
+                f32::INFINITY
+            }
+        };
+
+        let (mut next_x, mut next_y) = (edge(from.0, x, dx), edge(from.1, y, dy));
+        let (delta_x, delta_y) = ((1.0 / dx).abs(), (1.0 / dy).abs());
+
+        loop {
+            if let Some(element) = self.get(x, y) {
+                if matches(element) {
+                    return Some((x as u32, y as u32));
+                }
+            }
+
+            if (x, y) == end || next_x.min(next_y) > 1.0 {
+                return None;
+            }
+
+            if next_x < next_y {
+                x += step_x;
+                next_x += delta_x;
+            } else {
+                y += step_y;
+                next_y += delta_y;
+            }
+        }
+    }
+
+    // The closest matching position within radius of (x, y)
+    pub fn nearest(
+        &self,
+        x: u32,
+        y: u32,
+        radius: u32,
+        matches: impl Fn(&T) -> bool,
+    ) -> Option<(u32, u32)> {
+        let distance = |(px, py): (u32, u32)| {
+            let (dx, dy) = (px as i64 - x as i64, py as i64 - y as i64);
+            dx * dx + dy * dy
+        };
+
+        self.region(
+            x.saturating_sub(radius),
+            y.saturating_sub(radius),
+            radius * 2 + 1,
+            radius * 2 + 1,
+        )
+        .filter(|&(pos, element)| distance(pos) <= (radius as i64).pow(2) && matches(element))
+        .min_by_key(|&(pos, _)| distance(pos))
+        .map(|(pos, _)| pos)
+    }
+
+    // A layer of the same size made by applying f to every element
+    // The new layer uses the same kind of storage
+    pub fn map<U: Cell>(&self, mut f: impl FnMut(&T) -> U) -> Layer<U> {
+        let mut layer = Layer::with_storage(self.width, self.height, self.storage_kind());
+
+        for i in 0..self.len() {
+            layer.set_index(i, f(self.storage.get(i)));
+        }
+
+        layer
+    }
+}
+
+impl<T: Cell> Clone for Layer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            width: self.width,
+            height: self.height,
+            storage: self.storage.clone(),
+        }
+    }
+}
+
+// Layers are equal if their elements are, however they are stored
+impl<T: Cell> PartialEq for Layer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.iter().zip(other.iter()).all(|(a, b)| a.1 == b.1)
+    }
+}
+
+impl<T: Cell> Index<(u32, u32)> for Layer<T> {
+    type Output = T;
+
+    fn index(&self, index: (u32, u32)) -> &Self::Output {
+        self.storage.get(self.index_of(index.0, index.1))
+    }
+}
+
+impl<T: Cell> IndexMut<(u32, u32)> for Layer<T> {
+    fn index_mut(&mut self, index: (u32, u32)) -> &mut Self::Output {
+        let i = self.index_of(index.0, index.1);
+        self.storage.get_mut(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a layer from rows of text, top row first, with # as true
+    fn layer(text: &str) -> Layer<bool> {
+        let rows: Vec<&str> = text.split_whitespace().collect();
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        let mut layer = Layer::new(width, height);
+
+        for (i, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                layer[(x as u32, height - 1 - i as u32)] = c == '#';
+            }
+        }
+
+        layer
+    }
+
+    fn positions<'a>(iter: impl Iterator<Item = ((u32, u32), &'a bool)>) -> Vec<(u32, u32)> {
+        iter.map(|(pos, _)| pos).collect()
+    }
+
+    fn sorted(mut positions: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+        positions.sort();
+        positions
+    }
+
+    #[test]
+    fn neighbours_at_corners() {
+        let layer: Layer<bool> = Layer::new(4, 3);
+
+        assert_eq!(
+            positions(layer.neighbours(0, 0, Neighbourhood::Moore)),
+            [(0, 1), (1, 1), (1, 0)]
+        );
+        assert_eq!(
+            positions(layer.neighbours(0, 0, Neighbourhood::VonNeumann)),
+            [(0, 1), (1, 0)]
+        );
+
+        assert_eq!(
+            positions(layer.neighbours(3, 2, Neighbourhood::Moore)),
+            [(2, 2), (2, 1), (3, 1)]
+        );
+        assert_eq!(
+            positions(layer.neighbours(3, 2, Neighbourhood::VonNeumann)),
+            [(2, 2), (3, 1)]
+        );
+    }
+
+    #[test]
+    fn neighbours_at_edges() {
+        let layer: Layer<bool> = Layer::new(4, 3);
+
+        assert_eq!(
+            positions(layer.neighbours(1, 0, Neighbourhood::Moore)),
+            [(0, 1), (1, 1), (2, 1), (0, 0), (2, 0)]
+        );
+        assert_eq!(
+            positions(layer.neighbours(1, 0, Neighbourhood::VonNeumann)),
+            [(1, 1), (0, 0), (2, 0)]
+        );
+
+        assert_eq!(
+            positions(layer.neighbours(3, 1, Neighbourhood::Moore)),
+            [(2, 2), (3, 2), (2, 1), (2, 0), (3, 0)]
+        );
+        assert_eq!(
+            positions(layer.neighbours(3, 1, Neighbourhood::VonNeumann)),
+            [(3, 2), (2, 1), (3, 0)]
+        );
+
+        // Every neighbour is inside away from the edges
+        assert_eq!(layer.neighbours(1, 1, Neighbourhood::Moore).count(), 8);
+        assert_eq!(layer.neighbours(1, 1, Neighbourhood::VonNeumann).count(), 4);
+    }
+
+    #[test]
+    fn flood_fill_across_diagonal_gaps() {
+        let layer = layer(
+            "#...
+             .#..
+             ..#.
+             ...#",
+        );
+
+        assert_eq!(
+            layer.flood_fill(0, 3, Neighbourhood::VonNeumann, |&b| b),
+            [(0, 3)]
+        );
+        assert_eq!(
+            sorted(layer.flood_fill(0, 3, Neighbourhood::Moore, |&b| b)),
+            [(0, 3), (1, 2), (2, 1), (3, 0)]
+        );
+
+        // The empty cells on either side of the line only touch diagonally
+        let empty = |n| layer.flood_fill(1, 3, n, |&b| !b).len();
+        assert_eq!(empty(Neighbourhood::VonNeumann), 6);
+        assert_eq!(empty(Neighbourhood::Moore), 12);
+    }
+
+    #[test]
+    fn flood_fill_from_non_matching_cell() {
+        let layer = layer(
+            "#.
+             .#",
+        );
+
+        assert!(layer
+            .flood_fill(1, 1, Neighbourhood::Moore, |&b| b)
+            .is_empty());
+    }
+
+    #[test]
+    fn label_across_diagonal_gaps() {
+        let layer = layer(
+            "#..#
+             .#..
+             ....
+             ##.#",
+        );
+
+        let (labels, sizes) = layer.label(Neighbourhood::Moore, |&b| b);
+
+        assert_eq!(sizes, [2, 2, 1, 1]);
+        assert_eq!(labels[(0, 3)], labels[(1, 2)]);
+        assert_eq!(labels[(0, 0)], labels[(1, 0)]);
+        assert_ne!(labels[(0, 3)], labels[(0, 0)]);
+        assert_eq!(labels[(2, 2)], None);
+
+        let (labels, sizes) = layer.label(Neighbourhood::VonNeumann, |&b| b);
+
+        assert_eq!(sizes, [2, 1, 1, 1, 1]);
+        assert_ne!(labels[(0, 3)], labels[(1, 2)]);
+        assert_eq!(labels[(0, 0)], labels[(1, 0)]);
+    }
+
+    #[test]
+    fn raycast_along_axes() {
+        let layer = layer(
+            "..........
+             .....#.#..
+             ..........",
+        );
+
+        assert_eq!(layer.raycast((0.5, 1.5), (9.5, 1.5), |&b| b), Some((5, 1)));
+        assert_eq!(layer.raycast((9.5, 1.5), (0.5, 1.5), |&b| b), Some((7, 1)));
+
+        // Stops at the end of the ray
+        assert_eq!(layer.raycast((0.5, 1.5), (4.5, 1.5), |&b| b), None);
+
+        assert_eq!(layer.raycast((5.5, 0.5), (5.5, 2.5), |&b| b), Some((5, 1)));
+        assert_eq!(layer.raycast((6.5, 2.5), (6.5, 0.5), |&b| b), None);
+
+        // A ray that doesn't move only checks its own cell
+        assert_eq!(layer.raycast((7.5, 1.5), (7.5, 1.5), |&b| b), Some((7, 1)));
+    }
+
+    #[test]
+    fn raycast_along_diagonals() {
+        let layer = layer(
+            ".....
+             ...#.
+             #....
+             .....
+             ...#.",
+        );
+
+        assert_eq!(layer.raycast((0.5, 0.5), (4.5, 4.5), |&b| b), Some((3, 3)));
+        assert_eq!(layer.raycast((4.5, 4.5), (0.5, 0.5), |&b| b), Some((3, 3)));
+
+        // Walls the line only touches at a corner aren't hit
+        assert_eq!(layer.raycast((1.5, 4.5), (4.5, 1.5), |&b| b), None);
+
+        // Shallow lines
+        assert_eq!(layer.raycast((0.5, 1.5), (4.5, 3.5), |&b| b), Some((3, 3)));
+        assert_eq!(layer.raycast((4.5, 2.5), (0.5, 0.5), |&b| b), None);
+    }
+
+    #[test]
+    fn raycast_out_of_bounds() {
+        let layer = layer(
+            "....
+             #...
+             ....",
+        );
+
+        // Rays can start and end outside the layer
+        assert_eq!(layer.raycast((-3.5, 1.5), (2.5, 1.5), |&b| b), Some((0, 1)));
+        assert_eq!(layer.raycast((3.5, 1.5), (-8.5, 1.5), |&b| b), Some((0, 1)));
+        assert_eq!(layer.raycast((1.5, 2.5), (9.5, 10.5), |&b| b), None);
+
+        // Entirely outside
+        assert_eq!(layer.raycast((-5.5, -5.5), (-1.5, 7.5), |&b| b), None);
+        assert_eq!(layer.raycast((-2.5, 1.5), (-0.5, 1.5), |&b| b), None);
+    }
+
+    #[test]
+    fn nearest_with_radius_0() {
+        let layer = layer(
+            "...
+             #..
+             ..#",
+        );
+
+        assert_eq!(layer.nearest(2, 0, 0, |&b| b), Some((2, 0)));
+        assert_eq!(layer.nearest(1, 1, 0, |&b| b), None);
+    }
+
+    #[test]
+    fn nearest_with_no_match() {
+        let layer = layer(
+            "....#
+             .....
+             .....
+             .....
+             .....",
+        );
+
+        // (4, 4) is inside the square around (1, 1) but further than the radius
+        assert_eq!(layer.nearest(1, 1, 3, |&b| b), None);
+        assert_eq!(layer.nearest(1, 1, 5, |&b| b), Some((4, 4)));
+
+        let empty: Layer<bool> = Layer::new(5, 5);
+        assert_eq!(empty.nearest(2, 2, 10, |&b| b), None);
+    }
+
+    #[test]
+    fn nearest_picks_the_closest() {
+        let layer = layer(
+            "#....
+             .....
+             ....#
+             .....
+             .....",
+        );
+
+        assert_eq!(layer.nearest(2, 2, 4, |&b| b), Some((4, 2)));
+        assert_eq!(layer.nearest(0, 0, 10, |&b| b), Some((0, 4)));
+    }
+
+    #[test]
+    fn sparse_layers_dont_store_the_default() {
+        let mut layer: Layer<u32> = Layer::with_storage(4, 4, StorageKind::Sparse);
+
+        layer.set((1, 1), 5);
+        layer.set((2, 3), 7);
+        assert_eq!(layer.iter_stored_mut().count(), 2);
+
+        layer.set((1, 1), 0);
+        layer.fill_rect(2, 2, 2, 2, 0);
+        assert_eq!(layer.iter_stored_mut().count(), 0);
+        assert_eq!(layer[(1, 1)], 0);
+
+        // Elements written through indexing are stored until compacted
+        layer[(0, 0)] = 0;
+        assert_eq!(layer.iter_stored_mut().count(), 1);
+
+        layer.compact();
+        assert_eq!(layer.iter_stored_mut().count(), 0);
+    }
+
+    #[test]
+    fn sparse_mutable_iterators_only_visit_stored_elements() {
+        let mut layer: Layer<u32> = Layer::with_storage(8, 8, StorageKind::Sparse);
+        layer.set((1, 1), 1);
+        layer.set((6, 6), 2);
+
+        for (_, element) in layer.region_stored_mut(0, 0, 4, 4) {
+            *element += 10;
+        }
+
+        assert_eq!(layer.iter_stored_mut().count(), 2);
+        assert_eq!((layer[(1, 1)], layer[(6, 6)], layer[(2, 2)]), (11, 2, 0));
+    }
+
+    #[test]
+    fn dense_region_stored_mut_is_clipped() {
+        let mut layer: Layer<u32> = Layer::new(4, 4);
+
+        for (pos, element) in layer.region_stored_mut(2, 3, 5, 5) {
+            *element = pos.0 * 10 + pos.1;
+        }
+
+        assert_eq!(layer.iter().filter(|(_, e)| **e != 0).count(), 2);
+        assert_eq!((layer[(2, 3)], layer[(3, 3)]), (23, 33));
+    }
+
+    #[test]
+    fn storage_doesnt_affect_equality() {
+        let mut dense: Layer<u32> = Layer::new(3, 3);
+        let mut sparse = Layer::with_storage(3, 3, StorageKind::Sparse);
+
+        for layer in [&mut dense, &mut sparse] {
+            layer.set((0, 2), 4);
+            layer.set((1, 0), 9);
+        }
+
+        assert!(dense == sparse);
+        assert!(dense.map(|e| e * 2) == sparse.map(|e| e * 2));
+
+        sparse.set((2, 2), 1);
+        assert!(dense != sparse);
+    }
+
+    #[test]
+    fn loaded_sparse_layers_only_store_uncommon_elements() {
+        let mut layer: Layer<u32> = Layer::with_storage(6, 6, StorageKind::Sparse);
+        layer.fill(3);
+        layer.set((1, 4), 5);
+        layer.set((5, 0), 7);
+
+        let text = ron::to_string(&layer).unwrap();
+        let mut loaded: Layer<u32> = ron::from_str(&text).unwrap();
+
+        assert!(loaded == layer);
+        assert_eq!(loaded.storage_kind(), StorageKind::Sparse);
+        assert_eq!(loaded.iter_stored_mut().count(), 2);
+
+        // Dense layers still store everything
+        let dense: Layer<u32> = Layer::new(6, 6);
+        let mut loaded: Layer<u32> = ron::from_str(&ron::to_string(&dense).unwrap()).unwrap();
+        assert_eq!(loaded.iter_stored_mut().count(), 36);
+    }
+}
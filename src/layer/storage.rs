@@ -0,0 +1,160 @@
+// Ways of storing the elements of a layer. Dense storage keeps every
+// element, sparse storage only keeps those that differ from a default
+// value, which suits layers that are mostly empty
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+// Anything that can be stored in a layer
+pub trait Cell: Default + Clone + PartialEq + Send + Sync + 'static {}
+
+impl<T: Default + Clone + PartialEq + Send + Sync + 'static> Cell for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageKind {
+    Dense,
+    Sparse,
+}
+
+// Elements are addressed by their index in the layer
+#[derive(Clone)]
+pub enum Storage<T> {
+    Dense(Vec<T>),
+    Sparse(Sparse<T>),
+}
+
+impl<T: Cell> Storage<T> {
+    pub fn new(kind: StorageKind, len: usize) -> Self {
+        match kind {
+            StorageKind::Dense => Self::Dense(vec![T::default(); len]),
+            StorageKind::Sparse => Self::Sparse(Sparse {
+                len,
+                default: T::default(),
+                elements: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn kind(&self) -> StorageKind {
+        match self {
+            Self::Dense(_) => StorageKind::Dense,
+            Self::Sparse(_) => StorageKind::Sparse,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> &T {
+        match self {
+            Self::Dense(elements) => &elements[index],
+            Self::Sparse(sparse) => sparse.get(index),
+        }
+    }
+
+    // Sparse storage keeps the element until it is compacted, set doesn't
+    pub fn get_mut(&mut self, index: usize) -> &mut T {
+        match self {
+            Self::Dense(elements) => &mut elements[index],
+            Self::Sparse(sparse) => sparse.get_mut(index),
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        match self {
+            Self::Dense(elements) => elements[index] = value,
+            Self::Sparse(sparse) => sparse.set(index, value),
+        }
+    }
+
+    pub fn fill(&mut self, value: T) {
+        match self {
+            Self::Dense(elements) => elements.fill(value),
+            Self::Sparse(sparse) => sparse.fill(value),
+        }
+    }
+
+    // Every stored element with its index. Sparse storage only stores
+    // elements that differ from the default, in no particular order
+    pub fn iter_stored_mut(&mut self) -> Box<dyn Iterator<Item = (usize, &mut T)> + '_> {
+        match self {
+            Self::Dense(elements) => Box::new(elements.iter_mut().enumerate()),
+            Self::Sparse(sparse) => Box::new(sparse.elements.iter_mut().map(|(i, e)| (*i, e))),
+        }
+    }
+
+    // Contiguous elements, only available from dense storage
+    pub fn as_slice(&self) -> Option<&[T]> {
+        match self {
+            Self::Dense(elements) => Some(elements),
+            Self::Sparse(_) => None,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        match self {
+            Self::Dense(elements) => Some(elements),
+            Self::Sparse(_) => None,
+        }
+    }
+
+    // Release any memory that isn't needed
+    pub fn compact(&mut self) {
+        if let Self::Sparse(sparse) = self {
+            sparse.compact();
+        }
+    }
+}
+
+// Elements equal to the default aren't stored. Mutable access stores the
+// element until the storage is compacted
+#[derive(Clone)]
+pub struct Sparse<T> {
+    len: usize,
+    default: T,
+    elements: HashMap<usize, T>,
+}
+
+impl<T: Cell> Sparse<T> {
+    fn check(&self, index: usize) {
+        assert!(
+            index < self.len,
+            "Index {} outside of sparse storage",
+            index
+        );
+    }
+
+    fn get(&self, index: usize) -> &T {
+        self.check(index);
+        self.elements.get(&index).unwrap_or(&self.default)
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut T {
+        self.check(index);
+
+        self.elements
+            .entry(index)
+            .or_insert_with(|| self.default.clone())
+    }
+
+    // Writing the default removes the element instead of storing it
+    fn set(&mut self, index: usize, value: T) {
+        self.check(index);
+
+        if value == self.default {
+            self.elements.remove(&index);
+        } else {
+            self.elements.insert(index, value);
+        }
+    }
+
+    // The value becomes the new default, so nothing needs to be stored
+    fn fill(&mut self, value: T) {
+        self.default = value;
+        self.elements.clear();
+    }
+
+    fn compact(&mut self) {
+        let default = &self.default;
+        self.elements.retain(|_, e| e != default);
+        self.elements.shrink_to_fit();
+    }
+}
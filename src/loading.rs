@@ -0,0 +1,81 @@
+// Loading screen covering the world while it is generated in the background
+
+use std::sync::atomic::Ordering;
+
+use bevy::prelude::*;
+
+use crate::hud::{HUD_FONT, HUD_FONT_SIZE};
+use crate::terrain::bevy_connect::WorldGeneration;
+
+#[derive(Component)]
+pub struct LoadingScreen;
+
+#[derive(Component)]
+pub struct LoadingText;
+
+pub fn setup_loading_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgb(0.1, 0.1, 0.1).into(),
+            ..Default::default()
+        })
+        // Stops clicks reaching the world underneath
+        .insert(Interaction::default())
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load(HUD_FONT),
+                        font_size: HUD_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(LoadingText);
+        });
+}
+
+pub fn show_loading_screen(mut query: Query<&mut Style, With<LoadingScreen>>) {
+    for mut style in query.iter_mut() {
+        style.display = Display::Flex;
+    }
+}
+
+pub fn hide_loading_screen(mut query: Query<&mut Style, With<LoadingScreen>>) {
+    for mut style in query.iter_mut() {
+        style.display = Display::None;
+    }
+}
+
+// Show the pass being run
+pub fn update_loading_screen(
+    generation: Option<Res<WorldGeneration>>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+) {
+    // Only inserted at the end of the frame generation started on
+    let Some(generation) = generation else {
+        return;
+    };
+
+    let done = generation.progress.load(Ordering::Relaxed);
+    let total = generation.passes.len();
+
+    let mut status = format!("Generating world... {}/{}", done, total);
+
+    if let Some(pass) = generation.passes.get(done) {
+        status += &format!("\n{}", pass);
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = status.clone();
+    }
+}
@@ -2,10 +2,19 @@
 #![feature(drain_filter)]
 #![feature(variant_count)]
 
+pub mod args;
+pub mod autotile;
 pub mod camera;
 pub mod character;
+pub mod console;
+pub mod editor;
+pub mod hud;
+pub mod input;
 pub mod layer;
+pub mod loading;
+pub mod minimap;
 pub mod player;
+pub mod state;
 pub mod surrounds;
 pub mod terrain;
 pub mod tile;
@@ -15,24 +24,93 @@ use bevy_ecs_tilemap::TilemapPlugin;
 use bevy_prototype_debug_lines::DebugLinesPlugin;
 use bevy_rapier2d::prelude::*;
 
-use camera::*;
-use character::animation::update_animations;
-use character::collision::*;
-use character::animation::*;
-use player::*;
-use terrain::bevy_connect::setup_world;
+use args::Args;
+use camera::CameraPlugin;
+use character::CharacterPlugin;
+use console::ConsolePlugin;
+use editor::EditorPlugin;
+use hud::HudPlugin;
+use input::ActionPlugin;
+use minimap::MinimapPlugin;
+use player::PlayerPlugin;
+use state::*;
+use terrain::bench;
+use terrain::bevy_connect::*;
+use terrain::golden;
+use terrain::Terrain;
 
-// Values that will later be changed during world creation
-pub const CHUNK_COUNT: u32 = 1;
-pub const WORLD_SIZE: (u32, u32) = (64 * CHUNK_COUNT, 64);
-pub const WORLD_SEED: &str = "7";
+// Report a problem with the command line and exit
+fn exit_with_error(error: String) -> ! {
+    eprintln!("{}\n{}", error, args::USAGE);
+    std::process::exit(1);
+}
+
+// Create the world without bevy, used for generating and checking worlds from scripts
+fn run_headless(args: &Args, config: &WorldConfig) {
+    let terrain = match &args.load {
+        Some(path) => Terrain::load(path).unwrap_or_else(|e| exit_with_error(e)),
+        None => config.generate(),
+    };
+
+    println!(
+        "Created {}x{} world with seed {}",
+        terrain.width, terrain.height, terrain.seed
+    );
+
+    for stats in terrain.ore_stats() {
+        println!("{}", stats);
+    }
+}
 
 fn main() {
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| exit_with_error(e));
+
+    if args.help {
+        println!("{}", args::USAGE);
+        return;
+    }
+
+    // Generation snapshots are handled before anything else
+    if args.check_worldgen || args.bless_worldgen {
+        let result = if args.bless_worldgen {
+            golden::bless()
+        } else {
+            golden::check()
+        };
+
+        match result {
+            Ok(message) => println!("{}", message),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    // Mistakes in the tilesets would otherwise only show up when a tile is drawn
+    if let Err(e) = autotile::validate() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let config = args.world_config().unwrap_or_else(|e| exit_with_error(e));
+
+    if args.bench_worldgen {
+        println!("{}", bench::run(&config));
+        return;
+    }
+
+    if args.headless {
+        run_headless(&args, &config);
+        return;
+    }
+
     App::new()
         .insert_resource(ClearColor(Color::rgb(0.5, 0.7, 1.0)))
-        .insert_resource(CursorPos(Vec2::new(f32::INFINITY, f32::INFINITY)))
-        .insert_resource(CommandMode::ModifyTerrain)
-        .insert_resource(PathState::default())
+        .insert_resource(config)
+        .insert_resource(args)
         .add_plugins(
             DefaultPlugins
                 .set(WindowPlugin {
@@ -50,14 +128,16 @@ fn main() {
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(8.0))
         .add_plugin(RapierDebugRenderPlugin::default())
         .add_plugin(DebugLinesPlugin::default())
-        .add_startup_system(setup_sprite_sheets)
-        .add_startup_system(setup_camera)
-        .add_startup_system(setup_world)
-        .add_system(move_camera)
-        .add_system(update_command_mode)
-        .add_system(update_cursor_pos)
-        .add_system(update_animations)
-        .add_system(resolve_mouse_input)
-        .add_system(update_colliders)
+        .add_state(AppState::Loading)
+        .add_plugin(TerrainPlugin)
+        .add_plugin(CharacterPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(ActionPlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(MinimapPlugin)
+        .add_plugin(ConsolePlugin)
+        .add_system(toggle_pause)
         .run();
 }
@@ -0,0 +1,363 @@
+// Minimap and full-world map. The map texture has one pixel
+// per tile and is redrawn incrementally as tiles are modified
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::camera::{CameraSystem, CameraTarget, Zoom};
+use crate::character::CharacterId;
+use crate::input::{Action, ActionState};
+use crate::terrain::bevy_connect::{TilemapLayer, TILE_SIZE};
+use crate::terrain::changes::TileChanged;
+use crate::terrain::*;
+use crate::tile::*;
+
+// Width of the minimap in screen pixels, the height follows the world's aspect ratio
+pub const MINIMAP_WIDTH: f32 = 192.0;
+pub const MINIMAP_MARGIN: f32 = 10.0;
+
+// Percentage of the screen covered by the full-world map
+pub const FULL_MAP_SIZE: f32 = 90.0;
+
+// Size of character markers in screen pixels
+const MARKER_SIZE: f32 = 4.0;
+
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MinimapSystem {
+    // Redrawing changed tiles and switching to and from the full map
+    Update,
+
+    // Runs once the camera has moved so the view rectangle matches it
+    Overlay,
+}
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        // The world is created during startup, so it can only be drawn after
+        app.add_startup_system_to_stage(StartupStage::PostStartup, setup_minimap)
+            .add_system_set(
+                SystemSet::new()
+                    .label(MinimapSystem::Update)
+                    .with_system(update_minimap_texture)
+                    .with_system(toggle_map)
+                    .with_system(teleport_from_map),
+            )
+            .add_system(
+                update_map_overlay
+                    .label(MinimapSystem::Overlay)
+                    .after(MinimapSystem::Update)
+                    .after(CameraSystem::Movement),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct Minimap {
+    pub image: Handle<Image>,
+
+    // True when showing the full-world map
+    pub open: bool,
+}
+
+// The node displaying the map texture
+#[derive(Component)]
+pub struct MapNode;
+
+// Rectangle showing the area visible to the camera
+#[derive(Component)]
+pub struct MapView;
+
+// Marker for the character entity it holds
+#[derive(Component)]
+pub struct MapMarker(pub Entity);
+
+// Returns the colour of the front-most tile at x, y
+fn tile_colour(terrain: &Terrain, x: u32, y: u32) -> [u8; 4] {
+    for layer in [FRONT, MIDDLE] {
+        let tile = terrain.layers[layer][(x, y)];
+
+        if tile != Tile::EMPTY {
+            return TileDescriptor::from_id(tile.id).map_colour;
+        }
+    }
+
+    TileDescriptor::from_id(terrain.layers[BACK][(x, y)].id).map_colour
+}
+
+// Writes the colour of a single tile into the map texture
+fn draw_tile(terrain: &Terrain, image: &mut Image, x: u32, y: u32) {
+    // Images are stored top to bottom
+    let row = terrain.height - y - 1;
+    let i = ((row * terrain.width + x) * 4) as usize;
+
+    image.data[i..i + 4].copy_from_slice(&tile_colour(terrain, x, y));
+}
+
+// Converts a world position into a fraction across the map,
+// (0, 0) being the top left corner
+fn world_to_map(terrain: &Terrain, tm_transform: &Transform, pos: Vec2) -> Vec2 {
+    let tile_size = Vec2::new(TILE_SIZE.x, TILE_SIZE.y);
+    let origin = tm_transform.translation.truncate() - tile_size / 2.0;
+    let tile = (pos - origin) / tile_size;
+
+    Vec2::new(
+        tile.x / terrain.width as f32,
+        1.0 - tile.y / terrain.height as f32,
+    )
+}
+
+fn map_to_world(terrain: &Terrain, tm_transform: &Transform, frac: Vec2) -> Vec2 {
+    let tile_size = Vec2::new(TILE_SIZE.x, TILE_SIZE.y);
+    let origin = tm_transform.translation.truncate() - tile_size / 2.0;
+    let tile = Vec2::new(
+        frac.x * terrain.width as f32,
+        (1.0 - frac.y) * terrain.height as f32,
+    );
+
+    origin + tile * tile_size
+}
+
+fn map_style(terrain: &Terrain, open: bool) -> Style {
+    if open {
+        let margin = (100.0 - FULL_MAP_SIZE) / 2.0;
+
+        Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Percent(margin),
+                top: Val::Percent(margin),
+                ..Default::default()
+            },
+            size: Size::new(Val::Percent(FULL_MAP_SIZE), Val::Percent(FULL_MAP_SIZE)),
+            ..Default::default()
+        }
+    } else {
+        let height = MINIMAP_WIDTH * terrain.height as f32 / terrain.width as f32;
+
+        Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(MINIMAP_MARGIN),
+                top: Val::Px(MINIMAP_MARGIN),
+                ..Default::default()
+            },
+            size: Size::new(Val::Px(MINIMAP_WIDTH), Val::Px(height)),
+            ..Default::default()
+        }
+    }
+}
+
+// Draw the whole world and create the map's UI nodes
+pub fn setup_minimap(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: terrain.width,
+            height: terrain.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    for x in 0..terrain.width {
+        for y in 0..terrain.height {
+            draw_tile(&terrain, &mut image, x, y);
+        }
+    }
+
+    let image = images.add(image);
+
+    commands
+        .spawn(ImageBundle {
+            style: map_style(&terrain, false),
+            image: UiImage(image.clone()),
+            background_color: Color::WHITE.into(),
+            ..Default::default()
+        })
+        .insert(MapNode)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    background_color: Color::rgba(1.0, 1.0, 1.0, 0.25).into(),
+                    ..Default::default()
+                })
+                .insert(MapView);
+        });
+
+    commands.insert_resource(Minimap { image, open: false });
+}
+
+// Redraw tiles that have changed since the last frame
+pub fn update_minimap_texture(
+    terrain: Res<Terrain>,
+    minimap: Res<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    mut events: EventReader<TileChanged>,
+) {
+    if events.is_empty() {
+        return;
+    }
+
+    if let Some(image) = images.get_mut(&minimap.image) {
+        for change in events.iter() {
+            draw_tile(&terrain, image, change.pos.0, change.pos.1);
+        }
+    }
+}
+
+// Switch between the minimap and the full-world map
+pub fn toggle_map(
+    actions: Res<ActionState>,
+    terrain: Res<Terrain>,
+    mut minimap: ResMut<Minimap>,
+    mut query: Query<&mut Style, With<MapNode>>,
+) {
+    if !actions.just_pressed(Action::ToggleMap) {
+        return;
+    }
+
+    minimap.open = !minimap.open;
+
+    for mut style in query.iter_mut() {
+        *style = map_style(&terrain, minimap.open);
+    }
+}
+
+// Move the view rectangle and character markers
+pub fn update_map_overlay(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    terrain: Res<Terrain>,
+    cam_query: Query<(&Transform, &Zoom), With<Camera2d>>,
+    tm_query: Query<(&TilemapLayer, &Transform), Without<Camera2d>>,
+    map_query: Query<Entity, With<MapNode>>,
+    char_query: Query<(Entity, &Transform), (With<CharacterId>, Without<Camera2d>)>,
+    mut view_query: Query<&mut Style, (With<MapView>, Without<MapMarker>)>,
+    mut marker_query: Query<(Entity, &MapMarker, &mut Style), Without<MapView>>,
+) {
+    let Some((_, tm_transform)) = tm_query.iter().find(|(layer, _)| layer.0 == FRONT) else {
+        return;
+    };
+
+    let Ok(map_entity) = map_query.get_single() else {
+        return;
+    };
+
+    let window = windows.primary();
+    let (cam_transform, zoom) = cam_query.single();
+
+    // Camera view
+    let half_view = Vec2::new(window.width(), window.height()) / (2.0 * **zoom as f32);
+    let top_left = world_to_map(
+        &terrain,
+        tm_transform,
+        cam_transform.translation.truncate() + Vec2::new(-half_view.x, half_view.y),
+    );
+    let bottom_right = world_to_map(
+        &terrain,
+        tm_transform,
+        cam_transform.translation.truncate() + Vec2::new(half_view.x, -half_view.y),
+    );
+
+    for mut style in view_query.iter_mut() {
+        style.position.left = Val::Percent(top_left.x * 100.0);
+        style.position.top = Val::Percent(top_left.y * 100.0);
+        style.size = Size::new(
+            Val::Percent((bottom_right.x - top_left.x) * 100.0),
+            Val::Percent((bottom_right.y - top_left.y) * 100.0),
+        );
+    }
+
+    // Characters
+    for (entity, marker, mut style) in marker_query.iter_mut() {
+        if let Ok((_, transform)) = char_query.get(marker.0) {
+            let pos = world_to_map(&terrain, tm_transform, transform.translation.truncate());
+
+            style.position.left = Val::Percent(pos.x * 100.0);
+            style.position.top = Val::Percent(pos.y * 100.0);
+        } else {
+            // The character no longer exists
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (entity, _) in char_query.iter() {
+        if marker_query.iter().any(|(_, marker, _)| marker.0 == entity) {
+            continue;
+        }
+
+        let marker = commands
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Px(MARKER_SIZE), Val::Px(MARKER_SIZE)),
+                    ..Default::default()
+                },
+                background_color: Color::RED.into(),
+                ..Default::default()
+            })
+            .insert(MapMarker(entity))
+            .id();
+
+        commands.entity(map_entity).add_child(marker);
+    }
+}
+
+// Clicking on the full-world map moves the camera there
+pub fn teleport_from_map(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    windows: Res<Windows>,
+    terrain: Res<Terrain>,
+    minimap: Res<Minimap>,
+    map_query: Query<(&Node, &GlobalTransform), With<MapNode>>,
+    tm_query: Query<(&TilemapLayer, &Transform), Without<Camera2d>>,
+    target_query: Query<Entity, With<CameraTarget>>,
+    mut cam_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    if !minimap.open || !actions.just_pressed(Action::Primary) {
+        return;
+    }
+
+    let Some((_, tm_transform)) = tm_query.iter().find(|(layer, _)| layer.0 == FRONT) else {
+        return;
+    };
+
+    let window = windows.primary();
+
+    let (Some(cursor), Ok((node, node_transform))) =
+        (window.cursor_position(), map_query.get_single())
+    else {
+        return;
+    };
+
+    // UI co-ords start at the top of the window
+    let cursor = Vec2::new(cursor.x, window.height() - cursor.y);
+    let min = node_transform.translation().truncate() - node.size() / 2.0;
+    let frac = (cursor - min) / node.size();
+
+    if frac.cmplt(Vec2::ZERO).any() || frac.cmpgt(Vec2::ONE).any() {
+        return;
+    }
+
+    let pos = map_to_world(&terrain, tm_transform, frac);
+
+    let mut transform = cam_query.single_mut();
+    transform.translation = pos.extend(transform.translation.z);
+
+    // Stop following whatever was being followed
+    for entity in target_query.iter() {
+        commands.entity(entity).remove::<CameraTarget>();
+    }
+}
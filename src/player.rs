@@ -3,15 +3,45 @@ use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
 use bevy_prototype_debug_lines::DebugLines;
 
-use crate::camera::CursorPos;
+use crate::camera::{CameraSystem, CursorPos};
 use crate::character::animation::SpriteSheetHandles;
+use crate::input::{Action, ActionState};
+use crate::minimap::Minimap;
+use crate::state::AppState;
 use crate::terrain::bevy_connect::TilemapLayer;
+use crate::terrain::changes::ChangeCause;
 use crate::terrain::node::PathNode;
-use crate::terrain::Terrain;
+use crate::terrain::{Terrain, FRONT, TOTAL_LAYERS};
 use crate::character::*;
 use crate::tile::*;
 
-#[derive(Resource)]
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayerSystem {
+    // Changing mode and acting on mouse input
+    Input,
+}
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CommandMode::ModifyTerrain)
+            .init_resource::<PathState>()
+            .init_resource::<Brush>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .label(PlayerSystem::Input)
+                    .with_system(update_command_mode)
+                    .with_system(
+                        resolve_mouse_input
+                            .after(update_command_mode)
+                            .after(CameraSystem::Cursor),
+                    ),
+            );
+    }
+}
+
+#[derive(Resource, Debug)]
 pub enum CommandMode {
     ModifyTerrain,
     PathFinding,
@@ -24,6 +54,25 @@ pub struct PathState {
     pub start_entity: Option<Entity>,
     pub goal: PathNode,
     pub goal_entity: Option<Entity>,
+
+    // Length and cost of the last path found
+    pub result: Option<(usize, u32)>,
+}
+
+// The tile placed when modifying terrain
+#[derive(Resource)]
+pub struct Brush {
+    pub tile: TileId,
+    pub layer: usize,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            tile: TileId::Ground(Ground::Stone),
+            layer: FRONT,
+        }
+    }
 }
 
 impl PathState {
@@ -34,13 +83,13 @@ impl PathState {
         lines: &mut DebugLines,
     ) {
         // Generate and display path
-        println!("Path: start = {:?}\tgoal = {:?}", self.start, self.goal);
+        self.result = None;
+
+        if let Some((path, cost)) = terrain.find_path(&self.start, &self.goal) {
+            self.result = Some((path.len(), cost));
 
-        if let Some(path) = terrain.find_path(&self.start, &self.goal) {
             let mut prev = self.start;
             for node in path {
-                println!("Path: {:?}", node);
-
                 // Find the world co-ords
                 let start = Vec3::new(
                     prev.x as f32 * 8.0 + tm_transform.translation.x,
@@ -58,8 +107,6 @@ impl PathState {
 
                 prev = node;
             }
-        } else {
-            println!("No path found!")
         }
     }
 }
@@ -80,22 +127,38 @@ pub fn resolve_mouse_input(
         &Transform,
     )>,
     cursor: Res<CursorPos>,
-    mouse: Res<Input<MouseButton>>,
+    actions: Res<ActionState>,
+    minimap: Res<Minimap>,
+    ui_query: Query<&Interaction>,
     mode: Res<CommandMode>,
+    brush: Res<Brush>,
     handles: Res<SpriteSheetHandles>,
     asset_server: Res<AssetServer>,
 ) {
-    // NOTE: tm_query is ordered by layer, which is very useful for ensuring that only
-    //       the currently visible layer is acted upon, however this isn't guarenteed by
-    //       and may break in later versions
+    // Clicks on the full-world map are handled by the map
+    if minimap.open {
+        return;
+    }
 
-    if mouse.just_pressed(MouseButton::Left) {
+    // Ignore clicks on UI elements
+    if ui_query.iter().any(|i| *i != Interaction::None) {
+        return;
+    }
+
+    if actions.just_pressed(Action::Primary) {
         match *mode {
             // Remove a tile
             CommandMode::ModifyTerrain => {
-                for (_entity, tm_size, tm_grid_size, mut tm_storage, tm_layer, tm_transform) in
-                    tm_query.iter_mut()
-                {
+                // Front-most layers first so only the visible tile is removed
+                for layer in 0..TOTAL_LAYERS {
+                    let Some((_, tm_size, tm_grid_size, mut tm_storage, _, tm_transform)) =
+                        tm_query
+                            .iter_mut()
+                            .find(|(_, _, _, _, tm_layer, _)| tm_layer.0 == layer)
+                    else {
+                        continue;
+                    };
+
                     let world_pos = (tm_transform.compute_matrix().inverse()
                         * Vec4::from((cursor.0, 0.0, 1.0)))
                     .xy();
@@ -108,7 +171,13 @@ pub fn resolve_mouse_input(
                         &TilemapType::Square,
                     ) {
                         if terrain
-                            .remove_tile(&mut commands, &mut tm_storage, tm_layer.0, tile_pos)
+                            .remove_tile(
+                                &mut commands,
+                                &mut tm_storage,
+                                layer,
+                                tile_pos,
+                                ChangeCause::Edit,
+                            )
                             .is_some()
                         {
                             // Break from the loop if a tile has been removed
@@ -119,8 +188,10 @@ pub fn resolve_mouse_input(
             }
 
             CommandMode::PathFinding => {
-                let (_, tm_size, tm_grid_size, _, _, tm_transform) =
-                    tm_query.iter_mut().next().expect("No foreground tilemap");
+                let (_, tm_size, tm_grid_size, _, _, tm_transform) = tm_query
+                    .iter_mut()
+                    .find(|(_, _, _, _, layer, _)| layer.0 == FRONT)
+                    .expect("No foreground tilemap");
 
                 let world_pos = (tm_transform.compute_matrix().inverse()
                     * Vec4::from((cursor.0, 0.0, 1.0)))
@@ -157,12 +228,15 @@ pub fn resolve_mouse_input(
                 commands.spawn(CharacterBundle::from_id(CharacterId::HumanMale, cursor.0, &handles));
             }
         }
-    } else if mouse.just_pressed(MouseButton::Right) {
+    } else if actions.just_pressed(Action::Secondary) {
         match *mode {
             // place a tile
             CommandMode::ModifyTerrain => {
                 let (tm_entity, tm_size, tm_grid_size, mut tm_storage, tm_layer, tm_transform) =
-                    tm_query.iter_mut().next().expect("No foreground tilemap");
+                    tm_query
+                        .iter_mut()
+                        .find(|(_, _, _, _, layer, _)| layer.0 == brush.layer)
+                        .expect("No tilemap for the brush's layer");
 
                 let world_pos = (tm_transform.compute_matrix().inverse()
                     * Vec4::from((cursor.0, 0.0, 1.0)))
@@ -172,15 +246,22 @@ pub fn resolve_mouse_input(
                 if let Some(tile_pos) =
                     TilePos::from_world_pos(&world_pos, tm_size, tm_grid_size, &TilemapType::Square)
                 {
-                    if tm_storage.get(&tile_pos).is_none() {
+                    if TileDescriptor::from_id(brush.tile).dimensions.is_some() {
+                        // The whole footprint is checked before anything is placed
+                        terrain.insert_multi_tile(
+                            &mut commands,
+                            &mut tm_storage,
+                            tm_transform,
+                            tm_entity,
+                            brush.tile,
+                            tile_pos,
+                            ChangeCause::Edit,
+                        );
+                    } else if tm_storage.get(&tile_pos).is_none() {
                         // Create a tile
                         let tile = Tile::new(
-                            TileId::Ground(Ground::Stone),
-                            Some(
-                                terrain
-                                    .get_surrounds(tm_layer.0, tile_pos.x, tile_pos.y)
-                                    .get_texture_offset(),
-                            ),
+                            brush.tile,
+                            terrain.autotile_offset(brush.tile, tile_pos.x, tile_pos.y),
                         );
 
                         terrain.insert_tile(
@@ -191,14 +272,17 @@ pub fn resolve_mouse_input(
                             tm_layer.0,
                             tile_pos,
                             tile,
+                            ChangeCause::Edit,
                         );
                     }
                 }
             }
 
             CommandMode::PathFinding => {
-                let (_, tm_size, tm_grid_size, _, _, tm_transform) =
-                    tm_query.iter_mut().next().expect("No foreground tilemap");
+                let (_, tm_size, tm_grid_size, _, _, tm_transform) = tm_query
+                    .iter_mut()
+                    .find(|(_, _, _, _, layer, _)| layer.0 == FRONT)
+                    .expect("No foreground tilemap");
 
                 let world_pos = (tm_transform.compute_matrix().inverse()
                     * Vec4::from((cursor.0, 0.0, 1.0)))
@@ -236,12 +320,12 @@ pub fn resolve_mouse_input(
     }
 }
 
-pub fn update_command_mode(kbd: Res<Input<KeyCode>>, mut mode: ResMut<CommandMode>) {
-    if kbd.just_pressed(KeyCode::P) {
+pub fn update_command_mode(actions: Res<ActionState>, mut mode: ResMut<CommandMode>) {
+    if actions.just_pressed(Action::ModePathFinding) {
         *mode = CommandMode::PathFinding;
-    } else if kbd.just_pressed(KeyCode::M) {
+    } else if actions.just_pressed(Action::ModeTerrain) {
         *mode = CommandMode::ModifyTerrain;
-    } else if kbd.just_pressed(KeyCode::E) {
+    } else if actions.just_pressed(Action::ModePlaceEntity) {
         *mode = CommandMode::PlaceEntity;
     }
 }
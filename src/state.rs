@@ -0,0 +1,36 @@
+// Top level states of the game. Plugins only run their
+// systems in the states where they make sense
+
+use bevy::prelude::*;
+
+use crate::input::{Action, ActionState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    // Reading the save or starting generation
+    Loading,
+
+    // Waiting for the world to finish generating in the background
+    Generating,
+    InGame,
+
+    // The world is shown but nothing in it changes
+    Paused,
+}
+
+// Switch between playing and paused
+pub fn toggle_pause(actions: Res<ActionState>, mut state: ResMut<State<AppState>>) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+
+    let next = match state.current() {
+        AppState::InGame => AppState::Paused,
+        AppState::Paused => AppState::InGame,
+        _ => return,
+    };
+
+    if let Err(e) = state.set(next) {
+        warn!("Couldn't change state: {:?}", e);
+    }
+}
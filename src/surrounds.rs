@@ -1,7 +1,7 @@
 use bitflags::bitflags;
 
 // Info about the tiles surrounding a tile. This is stored as a u8 as it is useful for indexing
-// (See texture selection)
+// (See autotile.rs)
 //
 // Represented as such:
 // TL TM TR
@@ -32,266 +32,36 @@ impl Surrounds {
         count as u32
     }
 
-    pub fn get_texture_offset(&self) -> (u32, u32) {
-        TEXT_OFFSETS[self.bits() as usize]
+    // Parse space separated flag names, eg. "TL TM ML"
+    pub fn parse(names: &str) -> Result<Self, String> {
+        names
+            .split_whitespace()
+            .try_fold(Self::empty(), |surrounds, name| {
+                NAMES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, flag)| surrounds | *flag)
+                    .ok_or_else(|| format!("Unknown neighbour: {}", name))
+            })
+    }
+
+    pub fn names(&self) -> String {
+        NAMES
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 }
 
-const TEXT_OFFSETS: [(u32, u32); 256] = [
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (3, 1),
-    (3, 1),
-    (14, 1),
-    (14, 1),
-    (3, 1),
-    (0, 2),
-    (0, 2),
-    (0, 2),
-    (6, 2),
-    (6, 2),
-    (21, 1),
-    (19, 1),
-    (6, 2),
-    (6, 2),
-    (17, 1),
-    (1, 2),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (3, 1),
-    (3, 1),
-    (14, 1),
-    (14, 1),
-    (3, 1),
-    (3, 1),
-    (14, 1),
-    (0, 2),
-    (6, 2),
-    (6, 2),
-    (21, 1),
-    (19, 1),
-    (6, 2),
-    (6, 2),
-    (17, 1),
-    (1, 2),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (15, 0),
-    (15, 0),
-    (21, 0),
-    (17, 0),
-    (15, 0),
-    (15, 0),
-    (21, 0),
-    (17, 0),
-    (14, 0),
-    (14, 0),
-    (20, 1),
-    (20, 1),
-    (14, 0),
-    (14, 0),
-    (18, 1),
-    (18, 1),
-    (20, 0),
-    (20, 0),
-    (4, 1),
-    (10, 1),
-    (20, 0),
-    (20, 0),
-    (9, 1),
-    (11, 0),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (2, 0),
-    (2, 0),
-    (19, 0),
-    (2, 1),
-    (2, 0),
-    (2, 0),
-    (19, 0),
-    (2, 1),
-    (14, 0),
-    (14, 0),
-    (20, 1),
-    (20, 1),
-    (14, 0),
-    (14, 0),
-    (18, 1),
-    (18, 1),
-    (16, 0),
-    (16, 0),
-    (10, 0),
-    (13, 1),
-    (16, 0),
-    (16, 0),
-    (12, 1),
-    (7, 0),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (3, 1),
-    (3, 1),
-    (14, 1),
-    (14, 1),
-    (3, 1),
-    (3, 1),
-    (0, 2),
-    (0, 2),
-    (6, 2),
-    (6, 2),
-    (21, 1),
-    (19, 1),
-    (6, 2),
-    (6, 2),
-    (17, 1),
-    (1, 2),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (6, 0),
-    (6, 0),
-    (4, 2),
-    (4, 2),
-    (5, 1),
-    (5, 1),
-    (15, 1),
-    (2, 2),
-    (5, 1),
-    (5, 1),
-    (17, 1),
-    (2, 2),
-    (3, 1),
-    (3, 1),
-    (14, 1),
-    (14, 1),
-    (3, 1),
-    (3, 1),
-    (0, 2),
-    (0, 2),
-    (6, 2),
-    (6, 2),
-    (21, 1),
-    (19, 1),
-    (6, 2),
-    (6, 2),
-    (17, 1),
-    (1, 2),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (15, 0),
-    (15, 0),
-    (21, 0),
-    (17, 0),
-    (15, 0),
-    (15, 0),
-    (21, 0),
-    (2, 2),
-    (0, 0),
-    (0, 0),
-    (16, 1),
-    (16, 1),
-    (0, 0),
-    (0, 0),
-    (0, 1),
-    (0, 1),
-    (18, 0),
-    (18, 0),
-    (9, 0),
-    (12, 0),
-    (18, 0),
-    (17, 0),
-    (13, 0),
-    (8, 0),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (4, 0),
-    (4, 0),
-    (6, 1),
-    (6, 1),
-    (2, 0),
-    (2, 0),
-    (19, 0),
-    (2, 1),
-    (2, 0),
-    (2, 0),
-    (19, 0),
-    (2, 1),
-    (0, 0),
-    (0, 0),
-    (16, 1),
-    (16, 1),
-    (0, 0),
-    (0, 0),
-    (0, 1),
-    (0, 1),
-    (1, 0),
-    (1, 0),
-    (11, 1),
-    (7, 1),
-    (1, 0),
-    (1, 0),
-    (8, 1),
-    (1, 1),
+const NAMES: [(&str, Surrounds); 8] = [
+    ("TL", Surrounds::TL),
+    ("TM", Surrounds::TM),
+    ("TR", Surrounds::TR),
+    ("ML", Surrounds::ML),
+    ("MR", Surrounds::MR),
+    ("BL", Surrounds::BL),
+    ("BM", Surrounds::BM),
+    ("BR", Surrounds::BR),
 ];
@@ -0,0 +1,89 @@
+// Benchmark for world generation. Worlds of increasing size are
+// generated a number of times, serially and in parallel, and each pass
+// is timed, so the effect of a change to generation or to Layer can be measured
+
+use std::time::Duration;
+
+use super::bevy_connect::WorldConfig;
+
+// No. of worlds generated at each size, the times are averaged
+const RUNS: u32 = 5;
+
+const SIZES: [(u32, u32); 3] = [(256, 128), (512, 256), (1024, 512)];
+
+// Average time taken by each pass, generating serially or in parallel
+fn time_passes(config: &WorldConfig, parallel: bool) -> Vec<(&'static str, Duration)> {
+    let pipeline = config
+        .settings
+        .pipeline()
+        .expect("Passes are checked when settings are validated");
+
+    let mut totals: Vec<(&str, Duration)> = Vec::new();
+
+    for _ in 0..RUNS {
+        let mut terrain = config.empty();
+        terrain.parallel = parallel;
+
+        for (i, (name, time)) in pipeline.run_timed(&mut terrain).into_iter().enumerate() {
+            match totals.get_mut(i) {
+                Some((_, total)) => *total += time,
+                None => totals.push((name, time)),
+            }
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|(name, total)| (name, total / RUNS))
+        .collect()
+}
+
+fn ms(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
+
+// Returns a table of the average time taken by each pass at each size.
+// The serial times are the reference the parallel passes are compared to
+pub fn run(config: &WorldConfig) -> String {
+    let mut report = Vec::new();
+
+    for (width, height) in SIZES {
+        let config = WorldConfig {
+            width,
+            height,
+            ..config.clone()
+        };
+
+        let serial = time_passes(&config, false);
+        let parallel = time_passes(&config, true);
+
+        report.push(format!("{}x{}, seed {}:", width, height, config.seed));
+        report.push(format!(
+            "    {:<12} {:>13} {:>13} {:>8}",
+            "pass", "serial", "parallel", "speedup"
+        ));
+
+        let rows = serial
+            .iter()
+            .zip(parallel.iter())
+            .map(|(&(name, s), &(_, p))| (name, s, p));
+
+        let total = (
+            "total",
+            serial.iter().map(|(_, t)| *t).sum(),
+            parallel.iter().map(|(_, t)| *t).sum(),
+        );
+
+        for (name, s, p) in rows.chain([total]) {
+            report.push(format!(
+                "    {:<12} {:>10.3} ms {:>10.3} ms {:>7.2}x",
+                name,
+                ms(s),
+                ms(p),
+                ms(s) / ms(p).max(f64::EPSILON)
+            ));
+        }
+    }
+
+    report.join("\n")
+}
@@ -1,20 +1,50 @@
 // Module containing functions that tie worldgen into
 // bevy. These are seperated to keep the code modular
 
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+
 use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy_ecs_tilemap::prelude::*;
-
+use futures_lite::future;
+
+use crate::args::Args;
+use crate::autotile::TILESETS;
+use crate::character::CharacterId;
+use crate::loading::*;
+use crate::player::PathState;
+use crate::state::AppState;
+use crate::terrain::changes::*;
+use crate::terrain::grass::update_grass;
+use crate::terrain::node::update_path_tiles;
+use crate::terrain::objects::MultiTile;
 use crate::terrain::settings::*;
 use crate::terrain::*;
-use crate::tile::TILESET_SIZE;
+use crate::tile::{Sprite, TILESET_SIZE};
 use crate::*;
 
-const TILE_SIZE: TilemapTileSize = TilemapTileSize { x: 8.0, y: 8.0 };
+pub const TILE_SIZE: TilemapTileSize = TilemapTileSize { x: 8.0, y: 8.0 };
 const GRID_SIZE: TilemapGridSize = TilemapGridSize { x: 8.0, y: 8.0 };
 
+// Used when not given on the command line
+pub const DEFAULT_SEED: &str = "7";
+pub const DEFAULT_SIZE: (u32, u32) = (CHUNK_SIZE, CHUNK_SIZE);
+
 #[derive(Component)]
 pub struct TilemapLayer(pub usize);
 
+// The components that draw a sprite
+pub fn sprite_components(sprite: Sprite) -> (TileTextureIndex, TileFlip) {
+    let flip = TileFlip {
+        x: sprite.flip_x,
+        y: sprite.flip_y,
+        d: false,
+    };
+
+    (TileTextureIndex(sprite.index), flip)
+}
+
 impl Terrain {
     pub fn spawn_layer_tilemap(
         &self,
@@ -24,14 +54,7 @@ impl Terrain {
     ) {
         // Create atlas out of desired tileset
         let atlas = {
-            let handle;
-            if layer == FRONT {
-                handle = asset_server.load("Tiles.png");
-            } else if layer == MIDDLE {
-                handle = asset_server.load("MiddlegroundTiles.png");
-            } else {
-                handle = asset_server.load("BackgroundTiles.png");
-            }
+            let handle = asset_server.load(TILESETS[layer].path);
 
             TextureAtlas::from_grid(
                 handle,
@@ -73,15 +96,16 @@ impl Terrain {
                 }
 
                 let pos = TilePos { x, y };
+                let (texture_index, flip) =
+                    sprite_components(self.layers[layer][(x, y)].get_sprite(x, y));
 
                 let entity = commands
                     .spawn_empty()
                     .insert(TileBundle {
                         position: pos,
                         tilemap_id: TilemapId(tm_entity),
-                        texture_index: TileTextureIndex(
-                            self.layers[layer][(x, y)].get_texture_index(),
-                        ),
+                        texture_index,
+                        flip,
                         ..Default::default()
                     })
                     .insert(TransformBundle::from(Transform::from_xyz(
@@ -112,8 +136,8 @@ impl Terrain {
             .insert(TilemapLayer(layer));
     }
 
-    // Update the textures of tiles surround a tile
-    // Used when adding or removing tiles
+    // Update the textures of a tile and the tiles surrounding it
+    // Used by update_surrounding_tiles when tiles are added or removed
     pub fn update_surrounds(
         &mut self,
         commands: &mut Commands,
@@ -121,21 +145,32 @@ impl Terrain {
         pos: TilePos,
         layer: usize,
     ) {
-        for x in (pos.x as isize - 1)..=(pos.x as isize + 1) {
-            for y in (pos.y as isize - 1)..=(pos.y as isize + 1) {
-                // This has to be done out of the if-let - E0502
-                let new_offset = self
-                    .get_surrounds(layer, x as u32, y as u32)
-                    .get_texture_offset();
-
-                if let Some(tile) = self.layers[layer].get_mut(x, y) {
-                    if let Some(entity) = storage.get(&TilePos::new(x as u32, y as u32)) {
-                        tile.texture_offset = Some(new_offset);
-                        commands
-                            .entity(entity)
-                            .insert(TileTextureIndex(tile.get_texture_index()));
-                    }
-                }
+        // Tiles on other layers keep their own texture offsets
+        if TILESETS[layer].layout.is_none() {
+            return;
+        }
+
+        // Collected first as the layer is modified below
+        let positions: Vec<(u32, u32)> = std::iter::once((pos.x, pos.y))
+            .chain(
+                self.layers[layer]
+                    .neighbours(pos.x, pos.y, Neighbourhood::Moore)
+                    .map(|(pos, _)| pos),
+            )
+            .collect();
+
+        for (x, y) in positions {
+            // Trees and multi-tiles among the neighbours are left alone
+            let Some(new_offset) = self.autotile_offset(self.layers[layer][(x, y)].id, x, y) else {
+                continue;
+            };
+
+            if let Some(entity) = storage.get(&TilePos::new(x, y)) {
+                let tile = &mut self.layers[layer][(x, y)];
+                tile.texture_offset = Some(new_offset);
+                commands
+                    .entity(entity)
+                    .insert(sprite_components(tile.get_sprite(x, y)));
             }
         }
     }
@@ -150,13 +185,17 @@ impl Terrain {
         layer: usize,
         pos: TilePos,
         tile: Tile,
+        cause: ChangeCause,
     ) {
+        let (texture_index, flip) = sprite_components(tile.get_sprite(pos.x, pos.y));
+
         let entity = commands
             .spawn_empty()
             .insert(TileBundle {
                 position: pos,
                 tilemap_id: TilemapId(tm_entity),
-                texture_index: TileTextureIndex(tile.get_texture_index()),
+                texture_index,
+                flip,
                 ..Default::default()
             })
             .insert(TransformBundle::from(Transform::from_xyz(
@@ -168,33 +207,42 @@ impl Terrain {
 
         tm_storage.set(&pos, entity);
 
-        self.layers[layer][(pos.x, pos.y)] = tile;
-        self.update_surrounds(commands, tm_storage, pos, layer);
-
-        // Update Pathfinding nodes
+        self.set_tile(layer, (pos.x, pos.y), tile, cause);
+    }
 
-        // Check if this is now a valid walking tile
-        let tiles_above = [
-            self.layers[FRONT].get(pos.x as isize, pos.x as isize + 1),
-            self.layers[FRONT].get(pos.x as isize, pos.x as isize + 2),
-            self.layers[FRONT].get(pos.x as isize, pos.x as isize + 3),
-        ];
+    // Insert a multi-tile with its bottom left corner at pos, None if its footprint isn't free
+    pub fn insert_multi_tile(
+        &mut self,
+        commands: &mut Commands,
+        tm_storage: &mut TileStorage,
+        tm_transform: &Transform,
+        tm_entity: Entity,
+        id: TileId,
+        pos: TilePos,
+        cause: ChangeCause,
+    ) -> Option<MultiTile> {
+        let object = MultiTile::new(id, (pos.x, pos.y));
 
-        if (tiles_above[0].is_none() || tiles_above[0] == Some(&Tile::EMPTY))
-            && (tiles_above[1].is_none() || tiles_above[1] == Some(&Tile::EMPTY))
-            && (tiles_above[2].is_none() || tiles_above[2] == Some(&Tile::EMPTY))
-        {
-            self.nodes[(pos.x, pos.y)] = node::PathTile::Walkable;
+        if !self.can_place_multi_tile(&object) {
+            return None;
         }
 
-        // Check that this added tile hasn't obstructed any other nodes
-        if let Some(node) = self.nodes.get_mut(pos.x as isize, pos.y as isize - 1) {
-            *node = node::PathTile::NonWalkable;
+        for ((x, y), tile) in object.cells() {
+            self.insert_tile(
+                commands,
+                tm_storage,
+                tm_transform,
+                tm_entity,
+                id.layer(),
+                TilePos::new(x, y),
+                tile,
+                cause,
+            );
         }
 
-        if let Some(node) = self.nodes.get_mut(pos.x as isize, pos.y as isize - 2) {
-            *node = node::PathTile::NonWalkable;
-        }
+        self.track_multi_tile(object);
+
+        Some(object)
     }
 
     pub fn remove_tile(
@@ -203,66 +251,343 @@ impl Terrain {
         tm_storage: &mut TileStorage,
         layer: usize,
         pos: TilePos,
+        cause: ChangeCause,
     ) -> Option<()> {
         // Remove the tile's entity
         let entity = tm_storage.get(&pos)?;
 
+        // Multi-tiles are removed as a whole, and trunks take their foliage with them
+        let removed = if let Some(object) = self
+            .multi_tile_at(pos.x, pos.y)
+            .filter(|object| object.id.layer() == layer)
+        {
+            self.remove_multi_tile(pos.x, pos.y, cause);
+            object.cells().map(|(cell, _)| cell).collect()
+        } else if layer == MIDDLE
+            && self.layers[layer][(pos.x, pos.y)].id == TileId::Tree(Tree::Wood)
+        {
+            self.remove_trunk(pos.x, pos.y, cause)
+        } else {
+            Vec::new()
+        };
+
+        if !removed.is_empty() {
+            for (x, y) in removed {
+                let cell = TilePos::new(x, y);
+
+                if let Some(entity) = tm_storage.get(&cell) {
+                    tm_storage.remove(&cell);
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+
+            return Some(());
+        }
+
         tm_storage.remove(&pos);
         commands.entity(entity).despawn_recursive();
-        self.layers[layer][(pos.x, pos.y)] = Tile::EMPTY;
+        self.set_tile(layer, (pos.x, pos.y), Tile::EMPTY, cause);
 
-        // Update surrounding tiles - only on fore and background
-        if layer == MIDDLE {
-            return Some(());
+        Some(())
+    }
+}
+
+// Everything needed to generate a world
+#[derive(Resource, Clone)]
+pub struct WorldConfig {
+    pub seed: String,
+    pub width: u32,
+    pub height: u32,
+    pub settings: GenerationSettings,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            seed: DEFAULT_SEED.to_string(),
+            width: DEFAULT_SIZE.0,
+            height: DEFAULT_SIZE.1,
+            settings: GenerationSettings::forest(),
+        }
+    }
+}
+
+impl WorldConfig {
+    // The configuration an existing world was created with
+    pub fn from_terrain(terrain: &Terrain) -> Self {
+        Self {
+            seed: terrain.seed.clone(),
+            width: terrain.width,
+            height: terrain.height,
+            settings: terrain.settings.clone(),
         }
+    }
 
-        self.update_surrounds(commands, tm_storage, pos, layer);
-
-        // Update Pathfinding nodes
-
-        // This node could now belong to the air-space of three
-        // other nodes
-        let ground_tiles = [
-            self.layers[FRONT].get(pos.x as isize, pos.y as isize - 1),
-            self.layers[FRONT].get(pos.x as isize, pos.y as isize - 2),
-            self.layers[FRONT].get(pos.x as isize, pos.y as isize - 3),
-        ];
-
-        for i in 0..ground_tiles.len() {
-            // Check for a valid floor tile
-            if let Some(tile) = ground_tiles[i] && *tile != Tile::EMPTY {
-                // Now check the 3 air tiles above it
-                let y = (pos.y - 1 - i as u32) as isize;
-
-                let tiles_above = [
-                    self.layers[FRONT].get(pos.x as isize, y + 1),
-                    self.layers[FRONT].get(pos.x as isize, y as isize + 2),
-                    self.layers[FRONT].get(pos.x as isize, y as isize + 3),
-                ];
-
-                if (tiles_above[0].is_none() || tiles_above[0] == Some(&Tile::EMPTY))
-                    && (tiles_above[1].is_none() || tiles_above[1] == Some(&Tile::EMPTY))
-                    && (tiles_above[2].is_none() || tiles_above[2] == Some(&Tile::EMPTY))
-                {
-                    self.nodes[(pos.x, y as u32)] = PathTile::Walkable;
-                }
+    // A world with nothing in it, shown while generating
+    pub fn empty(&self) -> Terrain {
+        Terrain::new(
+            self.seed.clone(),
+            self.settings.clone(),
+            self.width,
+            self.height,
+        )
+    }
+
+    pub fn generate(&self) -> Terrain {
+        self.generate_with_progress(&AtomicUsize::new(0))
+    }
+
+    pub fn generate_with_progress(&self, progress: &AtomicUsize) -> Terrain {
+        let mut terrain = self.empty();
+        terrain.generate_with_progress(progress);
+        terrain
+    }
+}
+
+// Sent to throw away the current world and generate a new one from the WorldConfig
+pub struct RegenerateWorld;
+
+// A world being generated in the background
+#[derive(Resource)]
+pub struct WorldGeneration {
+    task: Task<Terrain>,
+
+    // No. of passes finished, out of those in passes
+    pub progress: Arc<AtomicUsize>,
+    pub passes: Vec<&'static str>,
+}
+
+fn start_generation(commands: &mut Commands, config: &WorldConfig) {
+    let progress = Arc::new(AtomicUsize::new(0));
+
+    let passes = config
+        .settings
+        .pipeline()
+        .map(|p| p.names())
+        .unwrap_or_default();
+
+    let (config, task_progress) = (config.clone(), progress.clone());
+
+    let task = AsyncComputeTaskPool::get()
+        .spawn(async move { config.generate_with_progress(&task_progress) });
+
+    commands.insert_resource(WorldGeneration {
+        task,
+        progress,
+        passes,
+    });
+}
+
+// Start generating the world, or load the saved world given on the command line
+pub fn setup_world(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    args: Res<Args>,
+    mut config: ResMut<WorldConfig>,
+) {
+    let saved = args.load.as_ref().and_then(|path| {
+        Terrain::load(path)
+            .map_err(|e| error!("{}, generating a new world", e))
+            .ok()
+    });
+
+    match saved {
+        Some(terrain) => {
+            for i in 0..TOTAL_LAYERS {
+                terrain.spawn_layer_tilemap(&mut commands, &asset_server, i)
             }
+
+            // Regenerating should recreate the saved world
+            *config = WorldConfig::from_terrain(&terrain);
+            commands.insert_resource(terrain);
         }
+        None => {
+            commands.insert_resource(config.empty());
+            start_generation(&mut commands, &config);
+        }
+    }
+}
 
-        Some(())
+// Wait for generation if setup_world started it
+pub fn finish_loading(
+    generation: Option<Res<WorldGeneration>>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let next = match generation {
+        Some(_) => AppState::Generating,
+        None => AppState::InGame,
+    };
+
+    if let Err(e) = state.set(next) {
+        warn!("Couldn't change state: {:?}", e);
     }
 }
 
-// Generate a tilemap with a randomly generated world
-pub fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // Generate world
-    let mut terrain = Terrain::new(None, GenerationSettings::FOREST, WORLD_SIZE.0, WORLD_SIZE.1);
+// Replace the world once it has finished generating
+pub fn finish_world_generation(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    old: Res<Terrain>,
+    generation: Option<ResMut<WorldGeneration>>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let Some(mut generation) = generation else {
+        return;
+    };
 
-    terrain.generate();
+    let Some(mut terrain) = future::block_on(future::poll_once(&mut generation.task)) else {
+        return;
+    };
+
+    commands.remove_resource::<WorldGeneration>();
 
     for i in 0..TOTAL_LAYERS {
         terrain.spawn_layer_tilemap(&mut commands, &asset_server, i)
     }
 
-    commands.insert_resource(terrain)
+    terrain.changes = Terrain::changes_between(&old, &terrain, ChangeCause::Generation);
+
+    commands.insert_resource(terrain);
+
+    if let Err(e) = state.set(AppState::InGame) {
+        warn!("Couldn't change state: {:?}", e);
+    }
+}
+
+// Remove everything that belonged to the old world and start generating a new one
+pub fn regenerate_world(
+    mut commands: Commands,
+    mut events: EventReader<RegenerateWorld>,
+    mut terrain: ResMut<Terrain>,
+    mut path_state: ResMut<PathState>,
+    config: Res<WorldConfig>,
+    generation: Option<Res<WorldGeneration>>,
+    mut state: ResMut<State<AppState>>,
+    tm_query: Query<(Entity, &TileStorage), With<TilemapLayer>>,
+    char_query: Query<Entity, With<CharacterId>>,
+) {
+    // Multiple requests in one frame only need one rebuild
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    // Wait for the current world to finish first
+    if generation.is_some() {
+        warn!("A world is already being generated");
+        return;
+    }
+
+    for (tm_entity, tm_storage) in tm_query.iter() {
+        for entity in tm_storage.iter().flatten() {
+            commands.entity(*entity).despawn_recursive();
+        }
+
+        commands.entity(tm_entity).despawn_recursive();
+    }
+
+    for entity in char_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for entity in [path_state.start_entity, path_state.goal_entity]
+        .into_iter()
+        .flatten()
+    {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    *path_state = PathState::default();
+
+    // Clear the world while the new one generates
+    let empty = config.empty();
+    let changes = Terrain::changes_between(&terrain, &empty, ChangeCause::Generation);
+
+    *terrain = empty;
+    terrain.changes = changes;
+
+    start_generation(&mut commands, &config);
+
+    if let Err(e) = state.set(AppState::Generating) {
+        warn!("Couldn't change state: {:?}", e);
+    }
+}
+
+// Changing a tile changes how the tiles around it connect to it
+pub fn update_surrounding_tiles(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    mut events: EventReader<TileChanged>,
+    mut tm_query: Query<(&mut TileStorage, &TilemapLayer)>,
+) {
+    // Generation autotiles every layer itself
+    let changes: Vec<&TileChanged> = events
+        .iter()
+        .filter(|change| change.cause != ChangeCause::Generation)
+        .collect();
+
+    if changes.is_empty() {
+        return;
+    }
+
+    for (mut storage, tm_layer) in tm_query.iter_mut() {
+        let layer = tm_layer.0;
+
+        for change in &changes {
+            // FRONT tiles that connect to the walls behind them change shape along with BACK
+            let behind = layer == FRONT && change.layer == BACK;
+
+            if change.layer == layer || behind {
+                let pos = TilePos::new(change.pos.0, change.pos.1);
+                terrain.update_surrounds(&mut commands, &mut storage, pos, layer);
+            }
+        }
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TerrainSystem {
+    // Starting and finishing world generation
+    Generation,
+
+    // The world changing by itself, eg. grass spreading
+    Growth,
+}
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RegenerateWorld>()
+            .add_event::<TileChanged>()
+            .add_startup_system(setup_world)
+            .add_startup_system(setup_loading_screen)
+            .add_system_set(SystemSet::on_update(AppState::Loading).with_system(finish_loading))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Generating).with_system(show_loading_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Generating)
+                    .label(TerrainSystem::Generation)
+                    .with_system(finish_world_generation)
+                    .with_system(update_loading_screen.after(finish_world_generation)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Generating).with_system(hide_loading_screen),
+            )
+            // Worlds can be regenerated while paused too
+            .add_system(
+                regenerate_world
+                    .label(TerrainSystem::Generation)
+                    .after(finish_world_generation),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .label(TerrainSystem::Growth)
+                    .after(TerrainSystem::Generation)
+                    .with_system(update_grass),
+            )
+            .add_system(update_path_tiles)
+            .add_system(update_surrounding_tiles)
+            .add_system_to_stage(CoreStage::PostUpdate, send_tile_changes);
+    }
 }
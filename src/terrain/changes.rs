@@ -0,0 +1,81 @@
+// Every change to a tile is recorded and sent as a TileChanged event,
+// so systems such as the minimap and pathfinding can react to changes
+// without being wired into the code that makes them
+
+use bevy::prelude::*;
+
+use super::*;
+
+// What caused a tile to change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeCause {
+    Generation, // The whole world was generated or cleared
+    Edit,       // Placed or removed by the player or the console
+    Growth,     // Grass spreading and decaying
+}
+
+#[derive(Debug, Clone)]
+pub struct TileChanged {
+    pub layer: usize,
+    pub pos: (u32, u32),
+    pub old: Tile,
+    pub new: Tile,
+    pub cause: ChangeCause,
+}
+
+impl Terrain {
+    // Set a tile, recording the change if it is different
+    pub fn set_tile(&mut self, layer: usize, pos: (u32, u32), tile: Tile, cause: ChangeCause) {
+        let old = self.layers[layer][pos];
+
+        if old == tile {
+            return;
+        }
+
+        self.layers[layer].set(pos, tile);
+
+        self.changes.push(TileChanged {
+            layer,
+            pos,
+            old,
+            new: tile,
+            cause,
+        });
+    }
+
+    // The changes needed to turn one world into another, used when a whole world is replaced
+    // Tiles outside of the old world are treated as empty
+    pub fn changes_between(old: &Terrain, new: &Terrain, cause: ChangeCause) -> Vec<TileChanged> {
+        let mut changes = Vec::new();
+
+        for layer in 0..TOTAL_LAYERS {
+            for ((x, y), tile) in new.layers[layer].iter() {
+                let old_tile = old.layers[layer]
+                    .get(x as isize, y as isize)
+                    .copied()
+                    .unwrap_or(Tile::EMPTY);
+
+                if old_tile != *tile {
+                    changes.push(TileChanged {
+                        layer,
+                        pos: (x, y),
+                        old: old_tile,
+                        new: *tile,
+                        cause,
+                    });
+                }
+            }
+        }
+
+        changes
+    }
+}
+
+// Send the changes recorded since the last frame
+pub fn send_tile_changes(mut terrain: ResMut<Terrain>, mut events: EventWriter<TileChanged>) {
+    if terrain.changes.is_empty() {
+        return;
+    }
+
+    events.send_batch(terrain.changes.drain(..));
+}
@@ -0,0 +1,198 @@
+// Regression check for world generation. Worlds are generated for a fixed
+// set of seeds and sizes and each layer is hashed, then compared against the
+// snapshots stored in GOLDEN_PATH. After an intentional change to generation
+// the snapshots are updated by blessing them. Hashes are taken over what each
+// cell holds, so changes to how layers are stored or saved don't affect them
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+pub const GOLDEN_PATH: &str = "golden/worldgen.ron";
+
+// Seed, width and height of every world that is checked
+const CASES: [(&str, u32, u32); 6] = [
+    ("7", 64, 64),
+    ("7", 128, 64),
+    ("42", 64, 64),
+    ("42", 256, 128),
+    ("csagame", 64, 96),
+    ("csagame", 192, 64),
+];
+
+// 64 bit FNV-1a, used as it is stable across platforms and versions
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    seed: String,
+    width: u32,
+    height: u32,
+    layers: [String; TOTAL_LAYERS],
+    nodes: String,
+}
+
+// Every cell of a layer in row order, each written as bytes by cell_bytes
+fn hash<T: Cell>(layer: &Layer<T>, cell_bytes: impl Fn(&T, &mut Vec<u8>)) -> String {
+    let mut bytes = Vec::new();
+
+    for y in 0..layer.height {
+        for x in 0..layer.width {
+            cell_bytes(&layer[(x, y)], &mut bytes);
+        }
+    }
+
+    let hash = bytes
+        .iter()
+        .fold(FNV_OFFSET, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME));
+
+    format!("{:016x}", hash)
+}
+
+// Tiles are written by name so reordering TileId doesn't change the hash
+fn tile_bytes(tile: &Tile, bytes: &mut Vec<u8>) {
+    bytes.extend(TileDescriptor::from_id(tile.id).name.as_bytes());
+    bytes.push(0);
+
+    match tile.texture_offset {
+        Some((x, y)) => {
+            bytes.push(1);
+            bytes.extend(x.to_le_bytes());
+            bytes.extend(y.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+
+    // Only shaped tiles add a byte, so the hashes of worlds without them are unchanged
+    if let Some(i) = TileShape::SHAPED.iter().position(|s| *s == tile.shape) {
+        bytes.push(i as u8 + 1);
+    }
+}
+
+fn path_tile_bytes(tile: &PathTile, bytes: &mut Vec<u8>) {
+    bytes.push(match tile {
+        PathTile::NonWalkable => 0,
+        PathTile::Walkable => 1,
+    });
+}
+
+fn snapshot(seed: &str, width: u32, height: u32) -> Snapshot {
+    let mut terrain = Terrain::new(
+        seed.to_string(),
+        GenerationSettings::forest(),
+        width,
+        height,
+    );
+
+    terrain.generate();
+
+    Snapshot {
+        seed: seed.to_string(),
+        width,
+        height,
+        layers: [
+            hash(&terrain.layers[FRONT], tile_bytes),
+            hash(&terrain.layers[MIDDLE], tile_bytes),
+            hash(&terrain.layers[BACK], tile_bytes),
+        ],
+        nodes: hash(&terrain.nodes, path_tile_bytes),
+    }
+}
+
+// Overwrite the stored snapshots with the current output
+pub fn bless() -> Result<String, String> {
+    let snapshots: Vec<Snapshot> = CASES.iter().map(|&(s, w, h)| snapshot(s, w, h)).collect();
+
+    let contents = ron::ser::to_string_pretty(&snapshots, ron::ser::PrettyConfig::default())
+        .map_err(|e| format!("Could not serialize snapshots: {}", e))?;
+
+    if let Some(dir) = std::path::Path::new(GOLDEN_PATH).parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {:?}: {}", dir, e))?;
+    }
+
+    fs::write(GOLDEN_PATH, contents)
+        .map_err(|e| format!("Could not write {}: {}", GOLDEN_PATH, e))?;
+
+    Ok(format!("Blessed {} worlds", snapshots.len()))
+}
+
+// Generate every world and report any that differ from their snapshot
+pub fn check() -> Result<String, String> {
+    let contents = fs::read_to_string(GOLDEN_PATH).map_err(|e| {
+        format!(
+            "Could not read {}: {}, run with --bless-worldgen to create it",
+            GOLDEN_PATH, e
+        )
+    })?;
+
+    let golden: Vec<Snapshot> =
+        ron::from_str(&contents).map_err(|e| format!("Invalid {}: {}", GOLDEN_PATH, e))?;
+
+    let mut failures = Vec::new();
+
+    for &(seed, width, height) in CASES.iter() {
+        let name = format!("seed {} at {}x{}", seed, width, height);
+
+        let Some(expected) = golden
+            .iter()
+            .find(|s| s.seed == seed && s.width == width && s.height == height)
+        else {
+            failures.push(format!("{}: no snapshot", name));
+            continue;
+        };
+
+        let actual = snapshot(seed, width, height);
+
+        for layer in 0..TOTAL_LAYERS {
+            if actual.layers[layer] != expected.layers[layer] {
+                failures.push(format!("{}: {} layer differs", name, LAYER_NAMES[layer]));
+            }
+        }
+
+        if actual.nodes != expected.nodes {
+            failures.push(format!("{}: path nodes differ", name));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(format!("All {} worlds match", CASES.len()))
+    } else {
+        failures.push(
+            "If this change is intentional, run with --bless-worldgen to update the snapshots"
+                .to_string(),
+        );
+
+        Err(failures.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same check as --check-worldgen, so it also runs with cargo test
+    #[test]
+    fn worldgen_matches_snapshots() {
+        if let Err(e) = check() {
+            panic!("{}", e);
+        }
+    }
+
+    #[test]
+    fn hashes_ignore_storage() {
+        let mut dense = Layer::with_storage(4, 3, StorageKind::Dense);
+        let mut sparse = Layer::with_storage(4, 3, StorageKind::Sparse);
+
+        let dirt = Tile::new(TileId::Ground(Ground::Dirt), Some((3, 1)));
+
+        for layer in [&mut dense, &mut sparse] {
+            layer.fill(Tile::EMPTY);
+            layer[(1, 2)] = dirt;
+        }
+
+        assert_eq!(hash(&dense, tile_bytes), hash(&sparse, tile_bytes));
+    }
+}
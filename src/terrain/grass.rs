@@ -0,0 +1,318 @@
+// Random tick system for grass. A bounded number of random
+// FRONT tiles are visited every frame, grass spreads onto exposed
+// dirt, decays back into dirt when covered and occasionally grows
+// surface decor on top of it
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use rand::Rng;
+
+use super::bevy_connect::{sprite_components, TilemapLayer};
+use super::changes::ChangeCause;
+use super::*;
+
+// A tile that has been modified by a random tick and must be
+// reflected in the tilemap
+pub struct TileChange {
+    pub layer: usize,
+    pub pos: (u32, u32),
+    pub tile: Tile,
+}
+
+impl Terrain {
+    // Visit a number of random tiles proportional to the world's area
+    pub fn random_tick(&mut self) -> Vec<TileChange> {
+        let mut changes = Vec::new();
+
+        // Scaled by the number of chunks in the world
+        let chunks = self.width * self.height / (CHUNK_SIZE * CHUNK_SIZE);
+        let ticks = chunks.max(1) * self.settings.grass.ticks;
+
+        for _ in 0..ticks {
+            let (x, y) = (
+                self.rng.gen_range(0..self.width),
+                self.rng.gen_range(0..self.height),
+            );
+
+            self.tick_grass(x, y, &mut changes);
+        }
+
+        changes
+    }
+
+    fn tick_grass(&mut self, x: u32, y: u32, changes: &mut Vec<TileChange>) {
+        if self.layers[FRONT][(x, y)].id != TileId::Ground(Ground::Grass) {
+            return;
+        }
+
+        // Grass can't survive without being exposed to the sky
+        if !self.is_exposed(x, y) {
+            if self.rng.gen::<f32>() < self.settings.grass.decay_chance {
+                self.set_ground(x, y, Ground::Dirt, changes);
+            }
+
+            return;
+        }
+
+        // Attempt to spread to a random neighbour
+        let (dx, dy) = (self.rng.gen_range(-1..=1), self.rng.gen_range(-1..=1));
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+
+        if let Some(tile) = self.layers[FRONT].get(nx, ny)
+            && tile.id == TileId::Ground(Ground::Dirt)
+            && self.is_exposed(nx as u32, ny as u32)
+            && self.rng.gen::<f32>() < self.settings.grass.spread_chance
+        {
+            self.set_ground(nx as u32, ny as u32, Ground::Grass, changes);
+        }
+
+        // Occasionally sprout decor on top
+        if self.rng.gen::<f32>() < self.settings.grass.decor_chance {
+            let decor = if self.rng.gen::<bool>() {
+                SurfaceDecor::GrassSmall
+            } else {
+                SurfaceDecor::GrassMedium
+            };
+
+            self.sprout_decor(TileId::SurfaceDecor(decor), x, y + 1, changes);
+        }
+    }
+
+    // Returns true if there is no solid tile directly above
+    fn is_exposed(&self, x: u32, y: u32) -> bool {
+        match self.layers[FRONT].get(x as isize, y as isize + 1) {
+            Some(tile) => *tile == Tile::EMPTY,
+            None => true,
+        }
+    }
+
+    // Changes the id of a ground tile while keeping its texture offset and shape
+    fn set_ground(&mut self, x: u32, y: u32, ground: Ground, changes: &mut Vec<TileChange>) {
+        let tile = Tile {
+            id: TileId::Ground(ground),
+            ..self.layers[FRONT][(x, y)]
+        };
+
+        self.set_tile(FRONT, (x, y), tile, ChangeCause::Growth);
+
+        changes.push(TileChange {
+            layer: FRONT,
+            pos: (x, y),
+            tile,
+        });
+    }
+
+    // Place a (possibly multi tile) decor with its bottom left corner at x, y
+    fn sprout_decor(&mut self, id: TileId, x: u32, y: u32, changes: &mut Vec<TileChange>) {
+        if TileDescriptor::from_id(id).dimensions.is_some() {
+            if let Some(object) = self.place_multi_tile(id, x, y, ChangeCause::Growth) {
+                changes.extend(object.cells().map(|(pos, tile)| TileChange {
+                    layer: MIDDLE,
+                    pos,
+                    tile,
+                }));
+            }
+
+            return;
+        }
+
+        let free =
+            |layer: usize| self.layers[layer].get(x as isize, y as isize) == Some(&Tile::EMPTY);
+
+        if !free(FRONT) || !free(MIDDLE) {
+            return;
+        }
+
+        let tile = Tile::new(id, None);
+        self.set_tile(MIDDLE, (x, y), tile, ChangeCause::Growth);
+
+        changes.push(TileChange {
+            layer: MIDDLE,
+            pos: (x, y),
+            tile,
+        });
+    }
+}
+
+// Run a random tick and reflect any changes in the tilemaps
+pub fn update_grass(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    mut tm_query: Query<(Entity, &mut TileStorage, &TilemapLayer, &Transform)>,
+) {
+    let changes = terrain.random_tick();
+
+    if changes.is_empty() {
+        return;
+    }
+
+    for (tm_entity, mut tm_storage, tm_layer, tm_transform) in tm_query.iter_mut() {
+        for change in changes.iter().filter(|c| c.layer == tm_layer.0) {
+            let pos = TilePos::new(change.pos.0, change.pos.1);
+
+            // Existing tiles only need a new texture
+            if let Some(entity) = tm_storage.get(&pos) {
+                commands
+                    .entity(entity)
+                    .insert(sprite_components(change.tile.get_sprite(pos.x, pos.y)));
+            } else {
+                terrain.insert_tile(
+                    &mut commands,
+                    &mut tm_storage,
+                    tm_transform,
+                    tm_entity,
+                    change.layer,
+                    pos,
+                    change.tile,
+                    ChangeCause::Growth,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRASS: Tile = Tile {
+        id: TileId::Ground(Ground::Grass),
+        texture_offset: None,
+        shape: TileShape::Full,
+    };
+
+    const DIRT: Tile = Tile {
+        id: TileId::Ground(Ground::Dirt),
+        texture_offset: None,
+        shape: TileShape::Full,
+    };
+
+    const STONE: Tile = Tile {
+        id: TileId::Ground(Ground::Stone),
+        texture_offset: None,
+        shape: TileShape::Full,
+    };
+
+    // An empty world where every chance is 0 unless a test sets it
+    fn world() -> Terrain {
+        let mut settings = GenerationSettings::forest();
+        settings.grass.spread_chance = 0.0;
+        settings.grass.decay_chance = 0.0;
+        settings.grass.decor_chance = 0.0;
+
+        Terrain::new("grass".to_string(), settings, 8, 8)
+    }
+
+    // Tick the same tile enough times that every random neighbour is tried
+    fn tick_many(terrain: &mut Terrain, x: u32, y: u32) -> Vec<TileChange> {
+        let mut changes = Vec::new();
+
+        for _ in 0..200 {
+            terrain.tick_grass(x, y, &mut changes);
+        }
+
+        changes
+    }
+
+    #[test]
+    fn covered_grass_decays() {
+        let mut terrain = world();
+        terrain.settings.grass.decay_chance = 1.0;
+        terrain.layers[FRONT][(3, 3)] = GRASS;
+        terrain.layers[FRONT][(3, 4)] = STONE;
+
+        let changes = tick_many(&mut terrain, 3, 3);
+
+        assert_eq!(terrain.layers[FRONT][(3, 3)], DIRT);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].pos, (3, 3));
+    }
+
+    #[test]
+    fn covered_grass_survives_without_decay() {
+        let mut terrain = world();
+        terrain.layers[FRONT][(3, 3)] = GRASS;
+        terrain.layers[FRONT][(3, 4)] = STONE;
+
+        assert!(tick_many(&mut terrain, 3, 3).is_empty());
+        assert_eq!(terrain.layers[FRONT][(3, 3)], GRASS);
+    }
+
+    #[test]
+    fn grass_spreads_to_exposed_dirt() {
+        let mut terrain = world();
+        terrain.settings.grass.spread_chance = 1.0;
+        terrain.layers[FRONT][(3, 3)] = GRASS;
+        terrain.layers[FRONT][(2, 3)] = DIRT;
+        terrain.layers[FRONT][(4, 2)] = DIRT;
+
+        tick_many(&mut terrain, 3, 3);
+
+        assert_eq!(terrain.layers[FRONT][(2, 3)], GRASS);
+        assert_eq!(terrain.layers[FRONT][(4, 2)], GRASS);
+    }
+
+    #[test]
+    fn grass_doesnt_spread_to_covered_dirt() {
+        let mut terrain = world();
+        terrain.settings.grass.spread_chance = 1.0;
+        terrain.layers[FRONT][(3, 3)] = GRASS;
+        terrain.layers[FRONT][(4, 3)] = DIRT;
+        terrain.layers[FRONT][(4, 4)] = STONE;
+
+        tick_many(&mut terrain, 3, 3);
+
+        assert_eq!(terrain.layers[FRONT][(4, 3)], DIRT);
+    }
+
+    #[test]
+    fn shape_is_kept_when_spreading() {
+        let mut terrain = world();
+        terrain.settings.grass.spread_chance = 1.0;
+        terrain.layers[FRONT][(3, 3)] = GRASS;
+        terrain.layers[FRONT][(2, 3)] = Tile {
+            shape: TileShape::Slope(Facing::Right),
+            ..DIRT
+        };
+
+        tick_many(&mut terrain, 3, 3);
+
+        assert_eq!(
+            terrain.layers[FRONT][(2, 3)],
+            Tile {
+                shape: TileShape::Slope(Facing::Right),
+                ..GRASS
+            }
+        );
+    }
+
+    #[test]
+    fn decor_sprouts_on_exposed_grass() {
+        let mut terrain = world();
+        terrain.settings.grass.decor_chance = 1.0;
+        terrain.layers[FRONT][(3, 3)] = GRASS;
+
+        let changes = tick_many(&mut terrain, 3, 3);
+
+        let decor = terrain.layers[MIDDLE][(3, 4)].id;
+        assert!(matches!(decor, TileId::SurfaceDecor(_)));
+        assert!(changes.iter().all(|c| c.layer == MIDDLE));
+
+        // Taller decor is tracked as one object
+        if decor == TileId::SurfaceDecor(SurfaceDecor::GrassMedium) {
+            assert_eq!(terrain.multi_tile_at(3, 5).map(|o| o.anchor), Some((3, 4)));
+        }
+    }
+
+    #[test]
+    fn other_tiles_are_ignored() {
+        let mut terrain = world();
+        terrain.settings.grass.spread_chance = 1.0;
+        terrain.settings.grass.decay_chance = 1.0;
+        terrain.layers[FRONT][(3, 3)] = DIRT;
+        terrain.layers[FRONT][(3, 4)] = STONE;
+        terrain.layers[FRONT][(2, 3)] = DIRT;
+
+        assert!(tick_many(&mut terrain, 3, 3).is_empty());
+    }
+}
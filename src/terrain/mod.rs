@@ -1,22 +1,33 @@
+pub mod bench;
 pub mod bevy_connect;
+pub mod changes;
+pub mod golden;
+pub mod grass;
 //pub mod layer;
 pub mod node;
+pub mod objects;
+pub mod ores;
+pub mod passes;
+pub mod save;
 pub mod settings;
 
+use std::sync::atomic::AtomicUsize;
+
 use bevy::prelude::Resource;
-use noise::{Fbm, MultiFractal, NoiseFn, Seedable, Value};
-use num_traits::FromPrimitive;
+use bevy::tasks::{ComputeTaskPool, ParallelSliceMut, TaskPool};
+use noise::{Fbm, MultiFractal, Seedable, Value};
 use rand::Rng;
 use rand_seeder::{rand_core::RngCore, Seeder, SipRng};
 
+use self::changes::*;
 use self::node::*;
+use self::objects::*;
 use self::settings::*;
 
+use crate::autotile::{layout_of, EDGES, TILESETS};
 use crate::layer::*;
 use crate::surrounds::Surrounds;
 use crate::tile::*;
-use crate::CHUNK_COUNT;
-use crate::WORLD_SEED;
 
 // Layer index constants
 pub const FRONT: usize = 0;
@@ -24,6 +35,33 @@ pub const MIDDLE: usize = 1;
 pub const BACK: usize = 2;
 pub const TOTAL_LAYERS: usize = 3;
 
+pub const LAYER_NAMES: [&str; TOTAL_LAYERS] = ["Front", "Middle", "Back"];
+
+// The middle layer only has decor and trees, so most of it is empty
+pub const LAYER_STORAGE: [StorageKind; TOTAL_LAYERS] =
+    [StorageKind::Dense, StorageKind::Sparse, StorageKind::Dense];
+
+// Width and height of a chunk of the world, noise is scaled to this
+pub const CHUNK_SIZE: u32 = 64;
+
+// Pool used to generate in parallel. Headless runs have no app to create it
+fn task_pool() -> &'static TaskPool {
+    ComputeTaskPool::init(TaskPool::default)
+}
+
+// Runs f on each chunk of columns, on the task pool unless generating serially
+fn for_each_chunk<T: Send>(
+    parallel: bool,
+    mut columns: &mut [T],
+    f: impl Fn(&mut [T]) + Send + Sync,
+) {
+    if parallel {
+        columns.par_chunk_map_mut(task_pool(), CHUNK_SIZE as usize, |chunk| f(chunk));
+    } else {
+        columns.chunks_mut(CHUNK_SIZE as usize).for_each(f);
+    }
+}
+
 #[derive(Resource)]
 pub struct Terrain {
     pub width: u32,
@@ -43,28 +81,30 @@ pub struct Terrain {
 
     // All the floors in the game
     pub nodes: Layer<PathTile>,
+
+    // The multi-tile covering each cell
+    pub objects: Layer<Option<MultiTile>>,
+
+    // Changes waiting to be sent as events
+    pub changes: Vec<TileChanged>,
+
+    // Generate on the task pool, the output is the same as generating serially
+    pub parallel: bool,
 }
 
 impl Terrain {
-    pub fn new(
-        seed: Option<String>,
-        settings: GenerationSettings,
-        width: u32,
-        height: u32,
-    ) -> Self {
-        // Use set seed if present
-        let seed = if let Some(seed) = seed {
-            seed
-        } else {
-            WORLD_SEED.to_string()
-        };
-
-        let layers = [
-            Layer::new(width, height),
-            Layer::new(width, height),
-            Layer::new(width, height),
+    pub fn new(seed: String, settings: GenerationSettings, width: u32, height: u32) -> Self {
+        let mut layers = [
+            Layer::with_storage(width, height, LAYER_STORAGE[FRONT]),
+            Layer::with_storage(width, height, LAYER_STORAGE[MIDDLE]),
+            Layer::with_storage(width, height, LAYER_STORAGE[BACK]),
         ];
 
+        // Start with an empty world, so it can be shown while generating
+        for layer in layers.iter_mut() {
+            layer.fill(Tile::EMPTY);
+        }
+
         let mut rng: SipRng = Seeder::from(seed.clone()).make_rng();
 
         let value = Value::new().set_seed(rng.next_u32());
@@ -76,6 +116,7 @@ impl Terrain {
             .set_octaves(settings.surface.octaves);
 
         let nodes = Layer::new(width, height);
+        let objects = Layer::with_storage(width, height, StorageKind::Sparse);
 
         Terrain {
             width,
@@ -87,420 +128,328 @@ impl Terrain {
             settings,
             layers,
             nodes,
+            objects,
+            changes: Vec::new(),
+            parallel: true,
         }
     }
 
+    // Run the generation passes chosen in the settings
     pub fn generate(&mut self) {
-        // Basic noise
-        for x in 0..self.width {
-            // Generate hills and mountains w/ fbm
-            let max_height = (self.surface_fbm.get([
-                (self.settings.surface.scale * x as f32 / self.width as f32 * CHUNK_COUNT as f32)
-                    as f64,
-                0.0,
-            ]) as f32
-                * self.settings.surface.amplitude
-                + self.settings.surface.height_offset * self.height as f32)
-                as u32;
-
-            for y in 0..self.height {
-                // The density at which a block is considered solid
-                // This is decreased higher up to create a more solid surface
-                let solid_density = self.settings.caves.solid_density
-                    - y as f32 / max_height as f32
-                        * self.settings.caves.solid_density
-                        * self.settings.caves.falloff;
-
-                // All tiles above the max height should be empty
-                // It is also good to make all tiles at max_height solid
-                if y <= max_height && self.value.get([x as f64, y as f64]) as f32 >= solid_density {
-                    self.layers[FRONT][(x, y)] = Tile::new(TileId::Ground(Ground::Stone), None);
-                } else {
-                    self.layers[FRONT][(x, y)] = Tile::EMPTY;
-                }
-
-                // Generate background slightly below terrain
-                if y <= max_height - self.settings.background_offset {
-                    self.layers[BACK][(x, y)] =
-                        Tile::new(TileId::Background(Background::Stone), None);
-                } else {
-                    self.layers[BACK][(x, y)] = Tile::EMPTY;
-                }
-            }
-        }
-
-        // Cellular automata smoothening
-        for _ in 0..self.settings.caves.smooth_iters {
-            self.layers[FRONT] = self.smooth();
-        }
-
-        // Dirt
-        for x in 0..self.width {
-            // Place dirt from this level up
-            let dirt_height = (self.surface_fbm.get([
-                (self.settings.surface.scale * x as f32 / self.width as f32 * 4.0) as f64,
-                0.0,
-            ]) as f32
-                * self.settings.surface.amplitude
-                + self.settings.dirt_height * self.height as f32
-                + self.rng.gen::<f32>() * self.settings.stone_jitter as f32)
-                as u32;
-
-            for y in dirt_height..self.height {
-                if self.layers[FRONT][(x, y)] != Tile::EMPTY {
-                    self.layers[FRONT][(x, y)] = Tile::new(TileId::Ground(Ground::Dirt), None);
-                }
-
-                if self.layers[BACK][(x, y)] != Tile::EMPTY {
-                    self.layers[BACK][(x, y)] =
-                        Tile::new(TileId::Background(Background::Dirt), None);
-                }
-            }
-        }
-
-        // Ores
-        for _ in 0..self.width / self.settings.ore_rate {
-            // Choose a random coordinate
-            let (x, y) = (
-                self.rng.gen_range(0..self.width - 1),
-                self.rng
-                    .gen_range(0..((self.height as f32 * self.settings.ore_height) as u32)),
-            );
-
-            // Don't overwrite empty tiles
-            if self.layers[FRONT][(x, y)] == Tile::EMPTY {
-                continue;
-            }
+        self.generate_with_progress(&AtomicUsize::new(0));
+    }
 
-            // Find an ore that can spawn at the current height
-            // This is done by randomly choosing ores until a suitable one is found
+    // Counts the passes as they finish
+    pub fn generate_with_progress(&mut self, progress: &AtomicUsize) {
+        let pipeline = self
+            .settings
+            .pipeline()
+            .expect("Passes are checked when settings are validated");
 
-            let mut desc = {
-                let selection = self.rng.gen_range(0..std::mem::variant_count::<Ore>());
-                TileDescriptor::from_id(TileId::Ore(Ore::from_usize(selection).unwrap()))
-            };
+        pipeline.run_with_progress(self, progress);
+    }
 
-            while ((desc.ore.unwrap().max_height * self.settings.ore_height * self.height as f32)
-                as u32)
-                < y
-            {
-                let selection = self.rng.gen_range(0..std::mem::variant_count::<Ore>());
-                desc = TileDescriptor::from_id(TileId::Ore(Ore::from_usize(selection).unwrap()));
-            }
+    fn generate_tree(&mut self, rng: &mut SipRng, x: u32, y: u32) -> Option<()> {
+        // Select a random trunk size
+        let trunk_height = rng.gen_range(self.settings.trees.trunk_height_range.clone());
 
-            let ore = desc.ore.unwrap();
+        // Generate foliage first, this has to go through multi tile checks
+        let foliage = TileDescriptor::from_id(TileId::Tree(Tree::Foliage));
 
-            // TODO: Ores might be broken - check
-            for w in (x as isize - ore.radius as isize)..(x as isize + ore.radius as isize) {
-                for h in (y as isize - ore.radius as isize)..(y as isize + ore.radius as isize) {
-                    if let Some(tile) = self.layers[FRONT].get_mut(w, h) {
-                        // Pass the distance to func
-                        let dist = ((y as f32 - w as f32).powf(2.0)
-                            + (y as f32 - h as f32).powf(2.0))
-                        .sqrt();
+        self.generate_multi_tile(
+            foliage.id,
+            x - foliage.dimensions.unwrap().0 / 2,
+            y + trunk_height,
+        )?;
 
-                        let gen_chance = dist / ore.radius as f32;
+        for h in 0..trunk_height {
+            let variant = rng.gen_range(0..self.settings.trees.trunk_variants - 1);
 
-                        if self.rng.gen::<f32>() > gen_chance {
-                            *tile = Tile::new(desc.id, None);
-                        }
-                    }
-                }
-            }
+            self.layers[MIDDLE].set(
+                (x, y + h),
+                Tile::new(TileId::Tree(Tree::Wood), Some((variant, 0))),
+            );
         }
 
-        // Grass - go through each column and change the first solid tile to grass
-        for x in 0..self.width {
-            for y in (0..self.height).rev() {
-                if self.layers[FRONT][(x, y)] == Tile::EMPTY {
-                    continue;
-                }
+        Some(())
+    }
 
-                self.layers[FRONT][(x, y)] = Tile::new(TileId::Ground(Ground::Grass), None);
+    // Returns None if generation was obsructed
+    fn generate_multi_tile(&mut self, id: TileId, x: u32, y: u32) -> Option<()> {
+        let object = MultiTile::new(id, (x, y));
 
-                break;
-            }
+        // Check for obstructions
+        if !self.can_place_multi_tile(&object) {
+            return None;
         }
 
-        // 'Initialize' the Middleground layer with TileId::Empty
-        for x in 0..self.width {
-            for y in 0..self.height {
-                self.layers[MIDDLE][(x, y)] = Tile::EMPTY
-            }
+        // All good, generate
+        for (pos, tile) in object.cells() {
+            self.layers[id.layer()].set(pos, tile);
         }
 
-        // TODO: The placement code for trees and surface decor
-        //       is very similar. Find a way to decouple it.
+        self.track_multi_tile(object);
 
-        // Trees
-        let mut x = 4;
-
-        // While loop is used as the iterator needs to be advanced in loop
-        while x < self.width - 1 {
-            // Skip if tree should not be generated here
-            if self.rng.gen::<f32>() <= self.settings.trees.spawn_rate {
-                x += 1;
-                continue;
-            }
-
-            for y in (0..self.height).rev() {
-                // Find a solid tile
-                if self.layers[FRONT][(x, y)] == Tile::EMPTY {
-                    continue;
-                }
+        Some(())
+    }
 
-                // Check the left and right side of the tile for edges
-                if self.layers[FRONT][(x - 1, y)] == Tile::EMPTY
-                    || self.layers[FRONT][(x + 1, y)] == Tile::EMPTY
-                {
-                    x += 1;
-                    break;
-                };
-
-                if self.generate_tree(x, y + 1).is_some() {
-                    x += 5;
-                } else {
-                    x += 1;
-                }
+    pub fn get_surrounds(&self, layer: usize, x: u32, y: u32) -> Surrounds {
+        let mut surrounds = Surrounds::empty();
 
-                break;
+        // Moore offsets are in the same order as the bits of Surrounds
+        for (i, (dx, dy)) in Neighbourhood::Moore.offsets().iter().enumerate() {
+            if let Some(t) = self.layers[layer].get(x as isize + dx, y as isize + dy) && t.id != TileId::Empty {
+                surrounds.toggle(Surrounds::from_bits_truncate(1 << i))
             }
         }
 
-        // Surface decor - start at one to avoid placing at the world's edge
-        let mut x = 1;
+        surrounds
+    }
 
-        // While loop is used as the iterator needs to be advanced in loop
-        while x < self.width - 2 {
-            // Skip if decor should not be generated here
-            if self.rng.gen::<f32>() < self.settings.decor.surface_rate {
-                x += 1;
-                continue;
-            }
+    // Neighbours in a layer whose tiles match a condition
+    fn get_matching(&self, layer: usize, x: u32, y: u32, f: impl Fn(&Tile) -> bool) -> Surrounds {
+        let mut surrounds = Surrounds::empty();
 
-            for y in (0..self.height).rev() {
-                // Find a solid tile
-                if self.layers[FRONT][(x, y)] == Tile::EMPTY {
-                    continue;
-                }
+        for (i, (dx, dy)) in Neighbourhood::Moore.offsets().iter().enumerate() {
+            let tile = self.layers[layer].get(x as isize + dx, y as isize + dy);
 
-                // Decor doesn't look great on the edge of terrain,
-                // So this is checked throughout this loop
+            if tile.map_or(false, &f) {
+                surrounds.toggle(Surrounds::from_bits_truncate(1 << i))
+            }
+        }
 
-                // x .  -> This looks ugly when tiled
-                // x x
-                // x x x
+        surrounds
+    }
 
-                // Check the left side of the tile for edges
-                if self.layers[FRONT][(x - 1, y)] == Tile::EMPTY {
-                    x += 1;
-                    break;
-                }
+    // Like get_surrounds, but only counting neighbours a tile at the position would join up with
+    pub fn get_connections(&self, id: TileId, x: u32, y: u32) -> Surrounds {
+        self.connections_of(TileDescriptor::from_id(id), x, y)
+    }
 
-                // Select a decor tile
-                let tile = {
-                    let selection = self.rng.gen_range(self.settings.decor.surface.clone());
+    fn connections_of(&self, desc: &TileDescriptor, x: u32, y: u32) -> Surrounds {
+        let layer = desc.id.layer();
 
-                    let tile = SurfaceDecor::from_usize(selection)
-                        .expect("Selection not within range of possible tiles");
+        let connected = self.get_matching(layer, x, y, |t| {
+            desc.connects(TileDescriptor::from_id(t.id))
+        });
 
-                    TileId::SurfaceDecor(tile)
-                };
+        if layer == FRONT && desc.connections.connects_behind {
+            connected | self.get_matching(BACK, x, y, |t| t.id != TileId::Empty)
+        } else {
+            connected
+        }
+    }
 
-                let desc = TileDescriptor::from_id(tile);
+    // Texture offset for a tile at a position from its neighbours, None if it isn't auto-tiled
+    pub fn autotile_offset(&self, id: TileId, x: u32, y: u32) -> Option<(u32, u32)> {
+        let layout = layout_of(id)?;
+        let desc = TileDescriptor::from_id(id);
+        let connections = self.connections_of(desc, x, y);
 
-                // TODO: rewrite this with if let Some =
+        if let Some(transition) = &desc.connections.transition {
+            let edges = layout.connectivity.edges(connections);
+            let against = self.get_matching(id.layer(), x, y, |t| {
+                TileDescriptor::from_id(t.id).connections.group == transition.group
+            });
 
-                // Single width tiles can be directly placed
-                if desc.dimensions.is_none() {
-                    // Check the right side of the tile for edges
-                    if self.layers[FRONT][(x + 1, y)] == Tile::EMPTY {
-                        x += 1;
-                        break;
-                    }
+            // Mixed edges keep the usual cells, or the transition would show against the others too
+            if !edges.is_empty() && against.contains(edges) {
+                let cell = EDGES.offset(connections);
 
-                    if self.layers[MIDDLE][(x, y + 1)] != Tile::EMPTY {
-                        x += 1;
-                        break;
-                    }
+                return Some((cell.0 + transition.offset.0, cell.1 + transition.offset.1));
+            }
+        }
 
-                    self.layers[MIDDLE][(x, y + 1)] = Tile::new(tile, None);
-                    x += 1;
-                    break;
-                }
+        Some(layout.offset(connections))
+    }
 
-                // Check the right side of the multi tile for edges
-                if self.layers[FRONT][(x + desc.dimensions.unwrap().0, y)] == Tile::EMPTY {
-                    x += 1;
-                    break;
+    // Smooth out randomly generated noise by making each tile more similar to it's neighbour
+    // Stores the result of the smooth in output to prevent tile_data from being corrupted in use
+    fn smooth(&self, output: &mut Layer<Tile>) {
+        let convert_min = self.settings.caves.convert_min;
+
+        let mut columns: Vec<(usize, &mut [Tile])> = output.columns_mut().enumerate().collect();
+
+        // Columns only depend on the previous layer, so the result is the same as smoothing serially
+        for_each_chunk(self.parallel, &mut columns, |chunk| {
+            for (x, column) in chunk.iter_mut() {
+                for (y, tile) in column.iter_mut().enumerate() {
+                    let w_count = self.get_surrounds(FRONT, *x as u32, y as u32).count();
+
+                    *tile = if w_count >= convert_min {
+                        Tile::new(TileId::Ground(Ground::Stone), None)
+                    } else {
+                        Tile::EMPTY
+                    };
                 }
+            }
+        });
+    }
+}
 
-                // Check that there is a solid floor beneath the decor
-                let mut okay = true;
-                for w in 1..desc.dimensions.unwrap().0 {
-                    if self.layers[FRONT][(x + w, y)] == Tile::EMPTY {
-                        okay = false;
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autotile::BLOB;
 
-                if !okay {
-                    x += 1;
-                    break;
-                }
+    fn generate(seed: &str, parallel: bool) -> Terrain {
+        let mut terrain = Terrain::new(seed.to_string(), GenerationSettings::forest(), 200, 96);
+        terrain.parallel = parallel;
+        terrain.generate();
 
-                // Attempt to generate a multi tile
-                if let Some(_) = self.generate_multi_tile(tile, x, y + 1) {
-                    x += desc.dimensions.unwrap().0;
-                } else {
-                    x += 1;
-                }
+        terrain
+    }
 
-                break;
+    #[test]
+    fn parallel_generation_matches_serial() {
+        for seed in ["7", "csagame"] {
+            let (serial, parallel) = (generate(seed, false), generate(seed, true));
+
+            for layer in 0..TOTAL_LAYERS {
+                assert!(
+                    serial.layers[layer] == parallel.layers[layer],
+                    "seed {}: {} layer differs",
+                    seed,
+                    LAYER_NAMES[layer]
+                );
             }
+
+            assert!(
+                serial.nodes == parallel.nodes,
+                "seed {}: path nodes differ",
+                seed
+            );
         }
+    }
 
-        // Match tiles to their surrounds
-        for x in 0..self.width {
-            for y in 0..self.height {
-                // Foreground
-                if self.layers[FRONT][(x, y)].id != TileId::Empty {
-                    self.layers[FRONT][(x, y)].texture_offset =
-                        Some(self.get_surrounds(FRONT, x, y).get_texture_offset());
-                }
+    fn trunks(terrain: &Terrain) -> Vec<(u32, u32)> {
+        terrain.layers[MIDDLE]
+            .iter()
+            .filter(|(_, tile)| tile.id == TileId::Tree(Tree::Wood))
+            .map(|(pos, _)| pos)
+            .collect()
+    }
 
-                // Background
-                if self.layers[BACK][(x, y)].id != TileId::Empty {
-                    self.layers[BACK][(x, y)].texture_offset =
-                        Some(self.get_surrounds(BACK, x, y).get_texture_offset());
-                }
+    #[test]
+    fn trunks_hold_up_foliage() {
+        let terrain = generate("7", true);
+        let trunks = trunks(&terrain);
+        assert!(!trunks.is_empty());
+
+        for (x, y) in trunks {
+            let mut top = y;
+            while terrain.layers[MIDDLE][(x, top)].id == TileId::Tree(Tree::Wood) {
+                top += 1;
             }
-        }
 
-        // Finally
-        // Generate pathfinding tiles
-        self.generate_path_tiles();
+            let foliage = terrain.multi_tile_at(x, top);
+            assert_eq!(foliage.map(|o| o.id), Some(TileId::Tree(Tree::Foliage)));
+            assert_eq!(foliage.unwrap().anchor.1, top);
+        }
     }
 
-    fn generate_tree(&mut self, x: u32, y: u32) -> Option<()> {
-        // Select a random trunk size
-        let trunk_height = self
-            .rng
-            .gen_range(self.settings.trees.trunk_height_range.clone());
+    #[test]
+    fn felling_a_trunk_removes_its_foliage() {
+        let mut terrain = generate("7", true);
+
+        // The bottom of a trunk at least two tiles high
+        let (x, y) = trunks(&terrain)
+            .into_iter()
+            .find(|&(x, y)| {
+                terrain.layers[MIDDLE][(x, y + 1)].id == TileId::Tree(Tree::Wood)
+                    && terrain.layers[MIDDLE][(x, y - 1)].id != TileId::Tree(Tree::Wood)
+            })
+            .unwrap();
+
+        let mut top = y;
+        while terrain.layers[MIDDLE][(x, top)].id == TileId::Tree(Tree::Wood) {
+            top += 1;
+        }
+        let foliage = terrain.multi_tile_at(x, top).unwrap();
 
-        // Generate foliage first, this has to go through multi tile checks
-        let foliage = TileDescriptor::from_id(TileId::Tree(Tree::Foliage));
+        let removed = terrain.remove_trunk(x, y + 1, ChangeCause::Edit);
 
-        self.generate_multi_tile(
-            foliage.id,
-            x - foliage.dimensions.unwrap().0 / 2,
-            y + trunk_height,
-        )?;
+        // The stump is left, everything above it goes
+        assert_eq!(terrain.layers[MIDDLE][(x, y)].id, TileId::Tree(Tree::Wood));
+        assert_eq!(removed.len() as u32, top - y - 1 + foliage.size.0 * foliage.size.1);
 
-        for h in 0..trunk_height {
-            let variant = self
-                .rng
-                .gen_range(0..self.settings.trees.trunk_variants - 1);
-
-            self.layers[MIDDLE][(x, y + h)] =
-                Tile::new(TileId::Tree(Tree::Wood), Some((variant, 0)));
+        for pos in removed {
+            assert_eq!(terrain.layers[MIDDLE][pos], Tile::EMPTY);
+            assert_eq!(terrain.multi_tile_at(pos.0, pos.1), None);
         }
-
-        Some(())
     }
 
-    // Returns None if generation was obsructed
-    fn generate_multi_tile(&mut self, id: TileId, x: u32, y: u32) -> Option<()> {
-        let desc = TileDescriptor::from_id(id);
-
-        // Unwrap the size. This should panic as a non-multi tile should be caught by devs
-        let size = desc
-            .dimensions
-            .unwrap_or_else(|| panic!("Tile {:?} is not a multi tile", id));
+    const STONE: TileId = TileId::Ground(Ground::Stone);
+    const DIRT: TileId = TileId::Ground(Ground::Dirt);
 
-        // Check for obstructions
-        for w in 0..size.0 {
-            for h in 0..size.1 {
-                if self.layers[FRONT][(x + w, y + h)] != Tile::EMPTY
-                    || self.layers[MIDDLE][(x + w, y + h)] != Tile::EMPTY
-                {
-                    return None;
-                }
-            }
-        }
+    // A 3x3 world with the given tile in the middle of the FRONT layer, surrounded by another
+    fn surrounded(middle: TileId, around: TileId) -> Terrain {
+        let mut terrain = Terrain::new("7".to_string(), GenerationSettings::forest(), 3, 3);
 
-        // All good, generate
-        for w in 0..size.0 {
-            for h in 0..size.1 {
-                self.layers[MIDDLE][(x + w, y + h)] = Tile::new(id, Some((w, size.1 - h - 1)));
-            }
-        }
+        terrain.layers[FRONT].fill(Tile::new(around, None));
+        terrain.layers[FRONT].set((1, 1), Tile::new(middle, None));
 
-        Some(())
+        terrain
     }
 
-    pub fn get_surrounds(&self, layer: usize, x: u32, y: u32) -> Surrounds {
-        let mut surrounds = Surrounds::empty();
+    #[test]
+    fn ores_join_up_with_their_rock() {
+        let iron = TileId::Ore(Ore::Iron);
 
-        // TL
-        if let Some(t) = self.layers[layer].get(x as isize - 1, y as isize + 1) && t.id != TileId::Empty {
-                surrounds.toggle(Surrounds::TL)
-        }
-
-        // TM
-        if let Some(t) = self.layers[layer].get(x as isize, y as isize + 1) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::TM)
-        }
+        for rock in [STONE, DIRT] {
+            let terrain = surrounded(iron, rock);
+            assert_eq!(terrain.get_connections(iron, 1, 1), Surrounds::all());
 
-        // TR
-        if let Some(t) = self.layers[layer].get(x as isize + 1, y as isize + 1) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::TR)
+            let terrain = surrounded(rock, iron);
+            assert_eq!(terrain.get_connections(rock, 1, 1), Surrounds::all());
         }
+    }
 
-        // ML
-        if let Some(t) = self.layers[layer].get(x as isize - 1, y as isize) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::ML)
-        }
+    #[test]
+    fn dirt_blends_into_stone() {
+        let terrain = surrounded(DIRT, STONE);
+        assert_eq!(terrain.get_connections(DIRT, 1, 1), Surrounds::all());
 
-        // MR
-        if let Some(t) = self.layers[layer].get(x as isize + 1, y as isize) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::MR)
-        }
+        let terrain = surrounded(STONE, DIRT);
+        assert_eq!(terrain.get_connections(STONE, 1, 1), Surrounds::empty());
+    }
 
-        // BL
-        if let Some(t) = self.layers[layer].get(x as isize - 1, y as isize - 1) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::BL)
-        }
+    #[test]
+    fn stone_uses_transitions_against_dirt() {
+        let mut terrain = surrounded(STONE, DIRT);
 
-        // BM
-        if let Some(t) = self.layers[layer].get(x as isize, y as isize - 1) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::BM)
-        }
+        // The lone transition cell, in row 15
+        assert_eq!(terrain.autotile_offset(STONE, 1, 1), Some((0, 9)));
 
-        // BR
-        if let Some(t) = self.layers[layer].get(x as isize + 1, y as isize - 1) && t.id != TileId::Empty {
-            surrounds.toggle(Surrounds::BR)
-        }
+        // Joined up with stone above, the rest is against dirt
+        terrain.layers[FRONT].set((1, 2), Tile::new(STONE, None));
+        assert_eq!(terrain.autotile_offset(STONE, 1, 1), Some((1, 9)));
 
-        surrounds
+        // Air below means the edges are mixed, so the usual cell is kept
+        terrain.layers[FRONT].set((1, 0), Tile::EMPTY);
+        assert_eq!(
+            terrain.autotile_offset(STONE, 1, 1),
+            Some(BLOB.offset(Surrounds::TM))
+        );
     }
 
-    // Smooth out randomly generated noise by making each tile more similar to it's neighbour
-    // Stores the result of the smooth in out to prevent tile_data from being corrupted in use
-    fn smooth(&self) -> Layer<Tile> {
-        let mut output = Layer::new(self.width, self.height);
-
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let w_count = self.get_surrounds(FRONT, x, y).count();
-
-                if w_count >= self.settings.caves.convert_min {
-                    output[(x, y)] = Tile::new(TileId::Ground(Ground::Stone), None)
-                } else if w_count < self.settings.caves.convert_min {
-                    output[(x, y)] = Tile::EMPTY
-                }
-            }
-        }
+    #[test]
+    fn tiles_can_connect_to_walls_behind() {
+        let mut terrain = surrounded(STONE, TileId::Empty);
+        let wall = Tile::new(TileId::Background(Background::Stone), None);
+        terrain.layers[BACK].set((1, 2), wall);
+
+        let stone = TileDescriptor::from_id(STONE);
+        assert_eq!(terrain.connections_of(stone, 1, 1), Surrounds::empty());
+
+        let behind = TileDescriptor {
+            connections: Connections {
+                connects_behind: true,
+                ..Connections::group(ConnectGroup::Rock)
+            },
+            ..*stone
+        };
 
-        output
+        assert_eq!(terrain.connections_of(&behind, 1, 1), Surrounds::TM);
     }
 }
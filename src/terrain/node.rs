@@ -1,8 +1,11 @@
+use bevy::prelude::{EventReader, ResMut};
 use pathfinding::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use super::changes::*;
 use super::*;
 
-#[derive(Default, PartialEq, Eq)]
+#[derive(Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PathTile {
     #[default]
     NonWalkable,
@@ -13,27 +16,50 @@ pub enum PathTile {
 const STRAIGHT_COST: u32 = 10;
 const DIAGONAL_COST: u32 = 14;
 
+// Walking up or down a slope, cheaper than jumping a step
+const SLOPE_COST: u32 = 11;
+
+// Walkable tiles need this many empty tiles above them
+const CLEARANCE: u32 = 3;
+
 impl Terrain {
     pub fn generate_path_tiles(&mut self) {
         for y in 0..self.height {
             for x in 0..self.width {
-                // Check if this node is walkable or not
-                let tiles_above = [
-                    self.layers[FRONT].get(x as isize, y as isize + 1),
-                    self.layers[FRONT].get(x as isize, y as isize + 2),
-                    self.layers[FRONT].get(x as isize, y as isize + 3),
-                ];
-
-                if self.layers[FRONT][(x, y)] != Tile::EMPTY
-                    && (tiles_above[0].is_none() || tiles_above[0] == Some(&Tile::EMPTY))
-                    && (tiles_above[1].is_none() || tiles_above[1] == Some(&Tile::EMPTY))
-                    && (tiles_above[2].is_none() || tiles_above[2] == Some(&Tile::EMPTY))
-                {
-                    self.nodes[(x, y)] = PathTile::Walkable;
-                }
+                self.update_path_tile(x, y);
             }
         }
     }
+
+    // A tile is walkable if it is solid with enough space above it
+    fn update_path_tile(&mut self, x: u32, y: u32) {
+        let clear = (1..=CLEARANCE).all(|h| {
+            let above = self.layers[FRONT].get(x as isize, (y + h) as isize);
+            above.is_none() || above == Some(&Tile::EMPTY)
+        });
+
+        self.nodes[(x, y)] = if self.layers[FRONT][(x, y)] != Tile::EMPTY && clear {
+            PathTile::Walkable
+        } else {
+            PathTile::NonWalkable
+        };
+    }
+}
+
+// Changing a tile affects whether it and the tiles below it can be walked on
+pub fn update_path_tiles(mut terrain: ResMut<Terrain>, mut events: EventReader<TileChanged>) {
+    for change in events.iter() {
+        // Generation creates its own path tiles
+        if change.layer != FRONT || change.cause == ChangeCause::Generation {
+            continue;
+        }
+
+        let (x, y) = change.pos;
+
+        for h in 0..=CLEARANCE.min(y) {
+            terrain.update_path_tile(x, y - h);
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
@@ -58,42 +84,49 @@ impl PathNode {
 
 impl Terrain {
     pub fn path_neighbours(&self, root: &PathNode) -> Vec<(PathNode, u32)> {
-        let mut neighbours = Vec::new();
-
-        for dx in [-1, 0, 1] {
-            for dy in [-1, 0, 1] {
-                // Skip the root node
-                if dx == 0 && dy == 0 {
-                    continue;
-                }
-
-                let (x, y) = (root.x as isize + dx, root.y as isize + dy);
-
-                // Only count walkable neighbours
-                if let Some(node) = self.nodes.get(x, y) && *node == PathTile::Walkable {
-                    // Determine how expensive the move will be
-                    let cost = if dy == 0 {
-                        STRAIGHT_COST
-                    } else {
-                        DIAGONAL_COST
-                    };
-
-                    neighbours.push((PathNode::new(x as u32, y as u32), cost));
-                }
-            }
+        self.nodes
+            .neighbours(root.x, root.y, Neighbourhood::Moore)
+            // Only count walkable neighbours
+            .filter(|(_, node)| **node == PathTile::Walkable)
+            .map(|((x, y), _)| {
+                // Determine how expensive the move will be
+                let cost = if y == root.y {
+                    STRAIGHT_COST
+                } else if self.is_slope_between(root, &PathNode::new(x, y)) {
+                    SLOPE_COST
+                } else {
+                    DIAGONAL_COST
+                };
+
+                (PathNode::new(x, y), cost)
+            })
+            .collect()
+    }
+
+    // Whether a diagonal move can be walked, because the higher tile slopes down to the lower
+    fn is_slope_between(&self, a: &PathNode, b: &PathNode) -> bool {
+        if a.x == b.x {
+            return false;
         }
 
-        neighbours
+        let (low, high) = if a.y < b.y { (a, b) } else { (b, a) };
+
+        let side = if low.x < high.x {
+            Facing::Left
+        } else {
+            Facing::Right
+        };
+
+        self.layers[FRONT][(high.x, high.y)].shape.eases_from(side)
     }
 
-    pub fn find_path(&self, start: &PathNode, goal: &PathNode) -> Option<Vec<PathNode>> {
-        let result = astar(
+    // Returns the path and its total cost
+    pub fn find_path(&self, start: &PathNode, goal: &PathNode) -> Option<(Vec<PathNode>, u32)> {
+        astar(
             start,
             |p| self.path_neighbours(p),
             |p| p.distance(goal),
             |p| *p == *goal,
-        );
-
-        result.map(|path| path.0)
+        )
     }
 }
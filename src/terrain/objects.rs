@@ -0,0 +1,148 @@
+// Multi-tiles such as trees and rock piles are tracked as single objects
+// anchored at their bottom left cell, so they are always placed and
+// removed as a whole instead of leaving orphaned cells behind
+
+use super::changes::ChangeCause;
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultiTile {
+    pub id: TileId,
+
+    // Bottom left cell
+    pub anchor: (u32, u32),
+    pub size: (u32, u32),
+}
+
+impl MultiTile {
+    pub fn new(id: TileId, anchor: (u32, u32)) -> Self {
+        // This should panic as a non-multi tile should be caught by devs
+        let size = TileDescriptor::from_id(id)
+            .dimensions
+            .unwrap_or_else(|| panic!("Tile {:?} is not a multi tile", id));
+
+        Self { id, anchor, size }
+    }
+
+    // Every cell in the footprint along with the tile that goes there
+    pub fn cells(&self) -> impl Iterator<Item = ((u32, u32), Tile)> + '_ {
+        (0..self.size.0).flat_map(move |w| {
+            (0..self.size.1).map(move |h| {
+                let pos = (self.anchor.0 + w, self.anchor.1 + h);
+
+                // Texture offsets are from the top left corner
+                (pos, Tile::new(self.id, Some((w, self.size.1 - h - 1))))
+            })
+        })
+    }
+}
+
+impl Terrain {
+    // The multi-tile covering a cell, if any
+    pub fn multi_tile_at(&self, x: u32, y: u32) -> Option<MultiTile> {
+        self.objects.get(x as isize, y as isize).copied().flatten()
+    }
+
+    // The whole footprint has to be inside the world and free
+    pub fn can_place_multi_tile(&self, object: &MultiTile) -> bool {
+        object.cells().all(|((x, y), _)| {
+            let (x, y) = (x as isize, y as isize);
+
+            self.layers[FRONT].get(x, y) == Some(&Tile::EMPTY)
+                && self.layers[MIDDLE].get(x, y) == Some(&Tile::EMPTY)
+        })
+    }
+
+    // Mark the footprint as belonging to an object, its tiles must already be set
+    pub fn track_multi_tile(&mut self, object: MultiTile) {
+        for (pos, _) in object.cells() {
+            self.objects.set(pos, Some(object));
+        }
+    }
+
+    // Returns None if the footprint is obstructed
+    pub fn place_multi_tile(
+        &mut self,
+        id: TileId,
+        x: u32,
+        y: u32,
+        cause: ChangeCause,
+    ) -> Option<MultiTile> {
+        let object = MultiTile::new(id, (x, y));
+
+        if !self.can_place_multi_tile(&object) {
+            return None;
+        }
+
+        for (pos, tile) in object.cells() {
+            self.set_tile(id.layer(), pos, tile, cause);
+        }
+
+        self.track_multi_tile(object);
+
+        Some(object)
+    }
+
+    // Remove the multi-tile covering a cell along with every other cell it covers
+    pub fn remove_multi_tile(&mut self, x: u32, y: u32, cause: ChangeCause) -> Option<MultiTile> {
+        let object = self.multi_tile_at(x, y)?;
+
+        for (pos, _) in object.cells() {
+            self.set_tile(object.id.layer(), pos, Tile::EMPTY, cause);
+            self.objects.set(pos, None);
+        }
+
+        Some(object)
+    }
+
+    // Trunks vary in height so they aren't multi-tiles. Removing a trunk cell fells
+    // the trunk above it along with the foliage it holds up
+    // Returns every cell that was removed
+    pub fn remove_trunk(&mut self, x: u32, y: u32, cause: ChangeCause) -> Vec<(u32, u32)> {
+        let mut removed = Vec::new();
+        let mut top = y;
+
+        while self.layers[MIDDLE]
+            .get(x as isize, top as isize)
+            .is_some_and(|t| t.id == TileId::Tree(Tree::Wood))
+        {
+            self.set_tile(MIDDLE, (x, top), Tile::EMPTY, cause);
+            removed.push((x, top));
+            top += 1;
+        }
+
+        if let Some(object) = self.multi_tile_at(x, top)
+            && object.id == TileId::Tree(Tree::Foliage)
+            && object.anchor.1 == top
+        {
+            self.remove_multi_tile(x, top, cause);
+            removed.extend(object.cells().map(|(pos, _)| pos));
+        }
+
+        removed
+    }
+
+    // Objects aren't saved, so they are found again from the tiles' texture offsets
+    // Footprints that aren't complete are left as loose tiles
+    pub fn find_multi_tiles(&mut self) {
+        let anchors: Vec<MultiTile> = self.layers[MIDDLE]
+            .iter()
+            .filter_map(|((x, y), tile)| {
+                let size = TileDescriptor::from_id(tile.id).dimensions?;
+
+                (tile.texture_offset == Some((0, size.1 - 1)))
+                    .then(|| MultiTile::new(tile.id, (x, y)))
+            })
+            .collect();
+
+        for object in anchors {
+            let complete = object.cells().all(|((x, y), tile)| {
+                self.layers[MIDDLE].get(x as isize, y as isize) == Some(&tile)
+            });
+
+            if complete {
+                self.track_multi_tile(object);
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+// Statistics on the ore in a world, used to balance ore generation
+
+use std::fmt;
+
+use super::*;
+
+pub struct OreStats {
+    pub id: TileId,
+    pub tiles: usize,
+    pub veins: usize,
+
+    // Lowest and highest tile of the ore, None if there are none
+    pub heights: Option<(u32, u32)>,
+}
+
+impl fmt::Display for OreStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = TileDescriptor::from_id(self.id).name;
+
+        match self.heights {
+            Some((low, high)) => write!(
+                f,
+                "{}: {} tiles in {} veins, heights {} to {}",
+                name, self.tiles, self.veins, low, high
+            ),
+            None => write!(f, "{}: none", name),
+        }
+    }
+}
+
+impl Terrain {
+    // Count every ore in the front layer. Touching tiles, including
+    // diagonally, are counted as one vein
+    pub fn ore_stats(&self) -> Vec<OreStats> {
+        let layer = &self.layers[FRONT];
+
+        TileDescriptor::ores()
+            .map(|(desc, _)| {
+                let (_, veins) = layer.label(Neighbourhood::Moore, |t| t.id == desc.id);
+
+                let heights = || {
+                    layer
+                        .iter()
+                        .filter(|(_, t)| t.id == desc.id)
+                        .map(|((_, y), _)| y)
+                };
+
+                OreStats {
+                    id: desc.id,
+                    tiles: veins.iter().sum(),
+                    veins: veins.len(),
+                    heights: heights().min().zip(heights().max()),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,914 @@
+// World generation is split into passes which are run in order by a
+// pipeline. Each pass gets its own rng seeded from the world seed and
+// the pass's name, so adding or removing a pass doesn't change the
+// randomness of the others
+
+use std::collections::VecDeque;
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use noise::{NoiseFn, Perlin};
+use num_traits::FromPrimitive;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use rand_seeder::{Seeder, SipRng};
+
+use super::*;
+
+// How quickly worms change direction and size as they travel
+const WORM_FREQUENCY: f64 = 0.05;
+
+// How far the noise can push the edge of a cavern in or out, relative to its size
+const CAVERN_ROUGHNESS: f32 = 0.3;
+const CAVERN_FREQUENCY: f64 = 0.15;
+
+// Size of the dirt pockets and stone boulders in the dirt/stone transition
+const TRANSITION_FREQUENCY: f64 = 0.2;
+
+// How far the noise can push the edge of an ore blob in or out, relative to its size
+const ORE_ROUGHNESS: f32 = 0.4;
+const ORE_FREQUENCY: f64 = 0.3;
+
+// Largest angle a streak of ore turns each tile
+const STREAK_TURN: f32 = 0.5;
+
+// Radius of the tunnels dug to connect caves
+const CONNECT_RADIUS: f32 = 1.0;
+
+// Chance of a step becoming a 22.5 degree slope across two tiles instead of a 45 degree one
+const GENTLE_SLOPE_CHANCE: f32 = 0.5;
+
+// Passes used when the settings don't give any
+pub const DEFAULT_PASSES: [&str; 12] = [
+    "surface",
+    "smooth",
+    "caverns",
+    "worms",
+    "connect",
+    "dirt",
+    "ores",
+    "grass",
+    "trees",
+    "decor",
+    "autotile",
+    "path_tiles",
+];
+
+pub trait GenerationPass: Send + Sync {
+    // Used to select the pass in settings and to seed its rng
+    fn name(&self) -> &'static str;
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng);
+}
+
+// Returns the built in pass with the given name
+pub fn pass_from_name(name: &str) -> Option<Box<dyn GenerationPass>> {
+    let pass: Box<dyn GenerationPass> = match name {
+        "surface" => Box::new(Surface),
+        "smooth" => Box::new(Smooth),
+        "caverns" => Box::new(Caverns),
+        "worms" => Box::new(Worms),
+        "connect" => Box::new(Connect),
+        "dirt" => Box::new(Dirt),
+        "ores" => Box::new(Ores),
+        "grass" => Box::new(Grass),
+        "trees" => Box::new(Trees),
+        "decor" => Box::new(Decor),
+        "slopes" => Box::new(Slopes),
+        "autotile" => Box::new(Autotile),
+        "path_tiles" => Box::new(PathTiles),
+        _ => return None,
+    };
+
+    Some(pass)
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn GenerationPass>>,
+}
+
+impl Pipeline {
+    pub fn from_names(names: &[impl AsRef<str>]) -> Result<Self, String> {
+        let mut pipeline = Self::default();
+
+        for name in names {
+            let name = name.as_ref();
+            let pass =
+                pass_from_name(name).ok_or_else(|| format!("Unknown generation pass: {}", name))?;
+
+            pipeline.passes.push(pass);
+        }
+
+        Ok(pipeline)
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|p| p.name() == name)
+    }
+
+    pub fn push(&mut self, pass: Box<dyn GenerationPass>) {
+        self.passes.push(pass);
+    }
+
+    pub fn insert(&mut self, index: usize, pass: Box<dyn GenerationPass>) {
+        self.passes.insert(index, pass);
+    }
+
+    // Returns false if there was no pass with the name
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.position(name) {
+            Some(i) => {
+                self.passes.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns false if there was no pass with the name
+    pub fn replace(&mut self, name: &str, pass: Box<dyn GenerationPass>) -> bool {
+        match self.position(name) {
+            Some(i) => {
+                self.passes[i] = pass;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub fn run(&self, terrain: &mut Terrain) {
+        self.run_with_progress(terrain, &AtomicUsize::new(0));
+    }
+
+    // Run every pass, returning how long each took
+    pub fn run_timed(&self, terrain: &mut Terrain) -> Vec<(&'static str, Duration)> {
+        let mut times = Vec::new();
+
+        for pass in &self.passes {
+            let mut rng: SipRng = Seeder::from((terrain.seed.as_str(), pass.name())).make_rng();
+
+            let start = Instant::now();
+            pass.run(terrain, &mut rng);
+            times.push((pass.name(), start.elapsed()));
+        }
+
+        times
+    }
+
+    // Counts the passes as they finish, so progress can be shown from another thread
+    pub fn run_with_progress(&self, terrain: &mut Terrain, progress: &AtomicUsize) {
+        for pass in &self.passes {
+            let mut rng: SipRng = Seeder::from((terrain.seed.as_str(), pass.name())).make_rng();
+            pass.run(terrain, &mut rng);
+
+            progress.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Sparse layers store every tile that was written to
+        for layer in terrain.layers.iter_mut() {
+            layer.compact();
+        }
+    }
+}
+
+// Hills and caves from noise, also clears the other layers
+pub struct Surface;
+
+impl GenerationPass for Surface {
+    fn name(&self) -> &'static str {
+        "surface"
+    }
+
+    fn run(&self, terrain: &mut Terrain, _: &mut SipRng) {
+        let (settings, value, surface_fbm) =
+            (&terrain.settings, &terrain.value, &terrain.surface_fbm);
+        let (height, parallel) = (terrain.height, terrain.parallel);
+
+        let [front, middle, back] = &mut terrain.layers;
+
+        // The middle layer may be sparse, so it is cleared separately
+        middle.fill(Tile::EMPTY);
+
+        let mut columns: Vec<_> = front
+            .columns_mut()
+            .zip(back.columns_mut())
+            .enumerate()
+            .collect();
+
+        // Columns are independent, so the result is the same as generating serially
+        for_each_chunk(parallel, &mut columns, |chunk| {
+            for (x, (front, back)) in chunk.iter_mut() {
+                let x = *x as u32;
+
+                // Generate hills and mountains w/ fbm
+                let max_height = (surface_fbm.get([
+                    (settings.surface.scale * x as f32 / CHUNK_SIZE as f32) as f64,
+                    0.0,
+                ]) as f32
+                    * settings.surface.amplitude
+                    + settings.surface.height_offset * height as f32)
+                    as u32;
+
+                for y in 0..height {
+                    // The density at which a block is considered solid
+                    // This is decreased higher up to create a more solid surface
+                    let solid_density = settings.caves.solid_density
+                        - y as f32 / max_height as f32
+                            * settings.caves.solid_density
+                            * settings.caves.falloff;
+
+                    // All tiles above the max height should be empty
+                    // It is also good to make all tiles at max_height solid
+                    front[y as usize] = if y <= max_height
+                        && value.get([x as f64, y as f64]) as f32 >= solid_density
+                    {
+                        Tile::new(TileId::Ground(Ground::Stone), None)
+                    } else {
+                        Tile::EMPTY
+                    };
+
+                    // Generate background slightly below terrain
+                    back[y as usize] = if y <= max_height - settings.background_offset {
+                        Tile::new(TileId::Background(Background::Stone), None)
+                    } else {
+                        Tile::EMPTY
+                    };
+                }
+            }
+        });
+    }
+}
+
+// Cellular automata smoothening
+pub struct Smooth;
+
+impl GenerationPass for Smooth {
+    fn name(&self) -> &'static str {
+        "smooth"
+    }
+
+    fn run(&self, terrain: &mut Terrain, _: &mut SipRng) {
+        // Reused between iterations rather than creating a new layer each time
+        let mut buffer = Layer::new(terrain.width, terrain.height);
+
+        for _ in 0..terrain.settings.caves.smooth_iters {
+            terrain.smooth(&mut buffer);
+            std::mem::swap(&mut terrain.layers[FRONT], &mut buffer);
+        }
+    }
+}
+
+// Remove every solid tile within radius of a point
+fn carve(layer: &mut Layer<Tile>, x: f32, y: f32, radius: f32) {
+    let r = radius.ceil() as isize;
+
+    for w in -r..=r {
+        for h in -r..=r {
+            if (w * w + h * h) as f32 > radius * radius {
+                continue;
+            }
+
+            if let Some(tile) = layer.get_mut(x.round() as isize + w, y.round() as isize + h) {
+                *tile = Tile::EMPTY;
+            }
+        }
+    }
+}
+
+// Large open caves, roughly elliptical with edges roughened by noise
+pub struct Caverns;
+
+impl GenerationPass for Caverns {
+    fn name(&self) -> &'static str {
+        "caverns"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        let caves = &terrain.settings.caves;
+        let noise = Perlin::new().set_seed(rng.next_u32());
+
+        let height = terrain.height as f32;
+        let heights = (caves.cavern_height.start * height)..(caves.cavern_height.end * height);
+
+        for i in 0..caves.caverns * terrain.width / CHUNK_SIZE {
+            let (cx, cy) = (
+                rng.gen_range(0.0..terrain.width as f32),
+                rng.gen_range(heights.clone()),
+            );
+
+            // Caverns are twice as wide as they are tall
+            let radius = rng.gen_range(caves.cavern_size.clone()) as f32;
+            let (rx, ry) = (radius * 2.0, radius);
+
+            for x in (cx - rx).floor() as isize..=(cx + rx).ceil() as isize {
+                for y in (cy - ry).floor() as isize..=(cy + ry).ceil() as isize {
+                    let (dx, dy) = ((x as f32 - cx) / rx, (y as f32 - cy) / ry);
+
+                    // Offset each cavern in the noise so they aren't the same shape
+                    let edge = 1.0
+                        + CAVERN_ROUGHNESS
+                            * noise.get([
+                                x as f64 * CAVERN_FREQUENCY,
+                                y as f64 * CAVERN_FREQUENCY + i as f64 * 100.0,
+                            ]) as f32;
+
+                    if (dx * dx + dy * dy).sqrt() > edge {
+                        continue;
+                    }
+
+                    if let Some(tile) = terrain.layers[FRONT].get_mut(x, y) {
+                        *tile = Tile::EMPTY;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Perlin worms, tunnels that wind down from the surface
+pub struct Worms;
+
+impl GenerationPass for Worms {
+    fn name(&self) -> &'static str {
+        "worms"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        let caves = &terrain.settings.caves;
+        let noise = Perlin::new().set_seed(rng.next_u32());
+
+        for i in 0..caves.worms * terrain.width / CHUNK_SIZE {
+            // Start on the surface
+            let start_x = rng.gen_range(0..terrain.width);
+
+            let Some(start_y) = (0..terrain.height)
+                .rev()
+                .find(|&y| terrain.layers[FRONT][(start_x, y)] != Tile::EMPTY)
+            else {
+                continue;
+            };
+
+            let (mut x, mut y) = (start_x as f32, start_y as f32);
+
+            for step in 0..caves.worm_length {
+                // Each worm follows its own row of noise, offset to avoid the zeros at integers
+                let t = step as f64 * WORM_FREQUENCY;
+                let row = i as f64 * 10.0 + 0.5;
+
+                let angle = -FRAC_PI_2 + noise.get([t, row]) as f32 * caves.worm_turn;
+                let radius = caves.worm_radius * (1.0 + 0.5 * noise.get([t, row + 5.0]) as f32);
+
+                carve(&mut terrain.layers[FRONT], x, y, radius);
+
+                x += angle.cos();
+                y += angle.sin();
+
+                if x < 0.0 || x >= terrain.width as f32 || y < 0.0 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Dig tunnels so that every large enough cave can be reached from the surface
+pub struct Connect;
+
+impl GenerationPass for Connect {
+    fn name(&self) -> &'static str {
+        "connect"
+    }
+
+    fn run(&self, terrain: &mut Terrain, _: &mut SipRng) {
+        let (width, height) = (terrain.width, terrain.height);
+        let min_region = terrain.settings.caves.min_region;
+        let front = &mut terrain.layers[FRONT];
+
+        // Label each region of connected empty tiles
+        let (regions, sizes) = front.label(Neighbourhood::VonNeumann, |t| *t == Tile::EMPTY);
+
+        // Regions open to the sky are already reachable
+        let mut reachable = vec![false; sizes.len()];
+
+        for region in regions.row(height - 1).flatten() {
+            reachable[*region] = true;
+        }
+
+        // Search outwards from the reachable tiles through solid ground,
+        // remembering which tile each was reached from
+        let mut distance: Layer<Option<u32>> = Layer::new(width, height);
+        let mut from: Layer<Option<(u32, u32)>> = Layer::new(width, height);
+        let mut queue = VecDeque::new();
+
+        for (pos, region) in regions.iter() {
+            if region.map_or(false, |r| reachable[r]) {
+                distance[pos] = Some(0);
+                queue.push_back(pos);
+            }
+        }
+
+        while let Some((x, y)) = queue.pop_front() {
+            let d = distance[(x, y)].unwrap();
+
+            for ((nx, ny), _) in regions.neighbours(x, y, Neighbourhood::VonNeumann) {
+                if distance[(nx, ny)].is_none() {
+                    distance[(nx, ny)] = Some(d + 1);
+                    from[(nx, ny)] = Some((x, y));
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+
+        // The tile of each region closest to a reachable one
+        let mut closest: Vec<Option<(u32, u32, u32)>> = vec![None; sizes.len()];
+
+        for ((x, y), region) in regions.iter() {
+            let (Some(region), Some(d)) = (*region, distance[(x, y)]) else {
+                continue;
+            };
+
+            if closest[region].map_or(true, |(best, _, _)| d < best) {
+                closest[region] = Some((d, x, y));
+            }
+        }
+
+        for (region, &size) in sizes.iter().enumerate() {
+            if reachable[region] || size < min_region as usize {
+                continue;
+            }
+
+            let Some((_, mut x, mut y)) = closest[region] else {
+                continue;
+            };
+
+            // Follow the search back to a reachable tile
+            while let Some((fx, fy)) = from[(x, y)] {
+                carve(front, x as f32, y as f32, CONNECT_RADIUS);
+                (x, y) = (fx, fy);
+            }
+        }
+    }
+}
+
+// Dirt above a noisy line, blended into the stone below over stone_blur tiles
+pub struct Dirt;
+
+impl GenerationPass for Dirt {
+    fn name(&self) -> &'static str {
+        "dirt"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        let settings = &terrain.settings;
+        let noise = Perlin::new().set_seed(rng.next_u32());
+
+        let blur = settings.stone_blur as f32;
+
+        for x in 0..terrain.width {
+            // The middle of the transition between stone and dirt
+            let dirt_height = terrain.surface_fbm.get([
+                (settings.surface.scale * x as f32 / terrain.width as f32 * 4.0) as f64,
+                0.0,
+            ]) as f32
+                * settings.surface.amplitude
+                + settings.dirt_height * terrain.height as f32
+                + rng.gen::<f32>() * settings.stone_jitter as f32;
+
+            for y in 0..terrain.height {
+                // Chance of dirt, rising from 0 to 1 across the band
+                let chance = if blur > 0.0 {
+                    ((y as f32 - dirt_height) / blur + 0.5).clamp(0.0, 1.0)
+                } else if y as f32 >= dirt_height {
+                    1.0
+                } else {
+                    0.0
+                };
+
+                if chance == 0.0 {
+                    continue;
+                }
+
+                // Noise is used instead of rng so dirt pockets and stone boulders clump together
+                let sample = (noise.get([
+                    x as f64 * TRANSITION_FREQUENCY,
+                    y as f64 * TRANSITION_FREQUENCY,
+                ]) as f32
+                    + 1.0)
+                    / 2.0;
+
+                if sample >= chance {
+                    continue;
+                }
+
+                // Both layers are changed together so the background matches
+                if terrain.layers[FRONT][(x, y)].id == TileId::Ground(Ground::Stone) {
+                    terrain.layers[FRONT][(x, y)] = Tile::new(TileId::Ground(Ground::Dirt), None);
+                }
+
+                if terrain.layers[BACK][(x, y)].id == TileId::Background(Background::Stone) {
+                    terrain.layers[BACK][(x, y)] =
+                        Tile::new(TileId::Background(Background::Dirt), None);
+                }
+            }
+        }
+    }
+}
+
+// Place a single ore tile, if the tile there can host it
+fn place_ore(layer: &mut Layer<Tile>, id: TileId, hosts: &[TileId], x: f32, y: f32) {
+    if let Some(tile) = layer
+        .get_mut(x.round() as isize, y.round() as isize)
+        .filter(|t| hosts.contains(&t.id))
+    {
+        *tile = Tile::new(id, None);
+    }
+}
+
+// Veins of ore, each ore is placed within its own band of heights
+pub struct Ores;
+
+impl GenerationPass for Ores {
+    fn name(&self) -> &'static str {
+        "ores"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        let ores: Vec<_> = TileDescriptor::ores().collect();
+
+        let Ok(weights) = WeightedIndex::new(ores.iter().map(|(_, ore)| ore.frequency)) else {
+            return;
+        };
+
+        let max_height = terrain.settings.ore_height * terrain.height as f32;
+        let noise = Perlin::new().set_seed(rng.next_u32());
+
+        for i in 0..terrain.width / terrain.settings.ore_rate {
+            let (desc, ore) = ores[weights.sample(rng)];
+
+            let heights = (ore.min_height * max_height)..(ore.max_height * max_height);
+
+            // The world may be too small for this ore
+            if heights.is_empty() {
+                continue;
+            }
+
+            let (mut x, mut y) = (
+                rng.gen_range(0..terrain.width) as f32,
+                rng.gen_range(heights),
+            );
+
+            let size = ore.size as f32;
+            let layer = &mut terrain.layers[FRONT];
+
+            match ore.shape {
+                VeinShape::Blob => {
+                    let r = ore.size as isize;
+
+                    for w in -r..=r {
+                        for h in -r..=r {
+                            let dist = ((w * w + h * h) as f32).sqrt() / size;
+
+                            // Roughen the edge with noise, offset so blobs aren't the same shape
+                            let edge = 1.0
+                                + ORE_ROUGHNESS
+                                    * noise.get([
+                                        (x as f64 + w as f64) * ORE_FREQUENCY,
+                                        (y as f64 + h as f64) * ORE_FREQUENCY + i as f64 * 100.0,
+                                    ]) as f32;
+
+                            if dist <= edge {
+                                place_ore(layer, desc.id, ore.hosts, x + w as f32, y + h as f32);
+                            }
+                        }
+                    }
+                }
+                VeinShape::Streak => {
+                    let mut angle = rng.gen_range(0.0..TAU);
+
+                    for _ in 0..ore.size {
+                        place_ore(layer, desc.id, ore.hosts, x, y);
+
+                        angle += rng.gen_range(-STREAK_TURN..=STREAK_TURN);
+                        x += angle.cos();
+                        y += angle.sin();
+                    }
+                }
+                VeinShape::Scattered => {
+                    for _ in 0..ore.size {
+                        let (w, h) = (rng.gen_range(-size..=size), rng.gen_range(-size..=size));
+                        place_ore(layer, desc.id, ore.hosts, x + w, y + h);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Go through each column and change the first solid tile to grass
+pub struct Grass;
+
+impl GenerationPass for Grass {
+    fn name(&self) -> &'static str {
+        "grass"
+    }
+
+    fn run(&self, terrain: &mut Terrain, _: &mut SipRng) {
+        for x in 0..terrain.width {
+            for y in (0..terrain.height).rev() {
+                if terrain.layers[FRONT][(x, y)] == Tile::EMPTY {
+                    continue;
+                }
+
+                terrain.layers[FRONT][(x, y)] = Tile::new(TileId::Ground(Ground::Grass), None);
+
+                break;
+            }
+        }
+    }
+}
+
+// TODO: The placement code for trees and surface decor
+//       is very similar. Find a way to decouple it.
+
+pub struct Trees;
+
+impl GenerationPass for Trees {
+    fn name(&self) -> &'static str {
+        "trees"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        let mut x = 4;
+
+        // While loop is used as the iterator needs to be advanced in loop
+        while x < terrain.width - 1 {
+            // Skip if tree should not be generated here
+            if rng.gen::<f32>() <= terrain.settings.trees.spawn_rate {
+                x += 1;
+                continue;
+            }
+
+            for y in (0..terrain.height).rev() {
+                // Find a solid tile
+                if terrain.layers[FRONT][(x, y)] == Tile::EMPTY {
+                    continue;
+                }
+
+                // Check the left and right side of the tile for edges
+                if terrain.layers[FRONT][(x - 1, y)] == Tile::EMPTY
+                    || terrain.layers[FRONT][(x + 1, y)] == Tile::EMPTY
+                {
+                    x += 1;
+                    break;
+                };
+
+                if terrain.generate_tree(rng, x, y + 1).is_some() {
+                    x += 5;
+                } else {
+                    x += 1;
+                }
+
+                break;
+            }
+        }
+    }
+}
+
+pub struct Decor;
+
+impl GenerationPass for Decor {
+    fn name(&self) -> &'static str {
+        "decor"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        // Start at one to avoid placing at the world's edge
+        let mut x = 1;
+
+        // While loop is used as the iterator needs to be advanced in loop
+        while x < terrain.width - 2 {
+            // Skip if decor should not be generated here
+            if rng.gen::<f32>() < terrain.settings.decor.surface_rate {
+                x += 1;
+                continue;
+            }
+
+            for y in (0..terrain.height).rev() {
+                // Find a solid tile
+                if terrain.layers[FRONT][(x, y)] == Tile::EMPTY {
+                    continue;
+                }
+
+                // Decor doesn't look great on the edge of terrain,
+                // So this is checked throughout this loop
+
+                // x .  -> This looks ugly when tiled
+                // x x
+                // x x x
+
+                // Check the left side of the tile for edges
+                if terrain.layers[FRONT][(x - 1, y)] == Tile::EMPTY {
+                    x += 1;
+                    break;
+                }
+
+                // Select a decor tile
+                let tile = {
+                    let selection = rng.gen_range(terrain.settings.decor.surface.clone());
+
+                    let tile = SurfaceDecor::from_usize(selection)
+                        .expect("Selection not within range of possible tiles");
+
+                    TileId::SurfaceDecor(tile)
+                };
+
+                let desc = TileDescriptor::from_id(tile);
+
+                // TODO: rewrite this with if let Some =
+
+                // Single width tiles can be directly placed
+                if desc.dimensions.is_none() {
+                    // Check the right side of the tile for edges
+                    if terrain.layers[FRONT][(x + 1, y)] == Tile::EMPTY {
+                        x += 1;
+                        break;
+                    }
+
+                    if terrain.layers[MIDDLE][(x, y + 1)] != Tile::EMPTY {
+                        x += 1;
+                        break;
+                    }
+
+                    terrain.layers[MIDDLE].set((x, y + 1), Tile::new(tile, None));
+                    x += 1;
+                    break;
+                }
+
+                // Check the right side of the multi tile for edges
+                if terrain.layers[FRONT][(x + desc.dimensions.unwrap().0, y)] == Tile::EMPTY {
+                    x += 1;
+                    break;
+                }
+
+                // Check that there is a solid floor beneath the decor
+                let mut okay = true;
+                for w in 1..desc.dimensions.unwrap().0 {
+                    if terrain.layers[FRONT][(x + w, y)] == Tile::EMPTY {
+                        okay = false;
+                    }
+                }
+
+                if !okay {
+                    x += 1;
+                    break;
+                }
+
+                // Attempt to generate a multi tile
+                if terrain.generate_multi_tile(tile, x, y + 1).is_some() {
+                    x += desc.dimensions.unwrap().0;
+                } else {
+                    x += 1;
+                }
+
+                break;
+            }
+        }
+    }
+}
+
+// Turn the steps along the surface into slopes and half tiles. Not run by
+// default, when used it should go after decor and before autotile
+pub struct Slopes;
+
+impl GenerationPass for Slopes {
+    fn name(&self) -> &'static str {
+        "slopes"
+    }
+
+    fn run(&self, terrain: &mut Terrain, rng: &mut SipRng) {
+        let is_empty =
+            |terrain: &Terrain, x: u32, y: u32| terrain.layers[FRONT][(x, y)] == Tile::EMPTY;
+
+        // Work out every change first so new shapes don't affect their neighbours
+        let mut changes = Vec::new();
+
+        for x in 2..terrain.width - 2 {
+            for y in 1..terrain.height - 1 {
+                let tile = terrain.layers[FRONT][(x, y)];
+
+                if tile == Tile::EMPTY
+                    || !tile.shape.is_full()
+                    || !TileDescriptor::from_id(tile.id).shaped
+                {
+                    continue;
+                }
+
+                // Only the surface is shaped, anything on top would be left floating
+                if !is_empty(terrain, x, y + 1) || terrain.layers[MIDDLE][(x, y + 1)] != Tile::EMPTY
+                {
+                    continue;
+                }
+
+                let step_left = is_empty(terrain, x - 1, y);
+                let step_right = is_empty(terrain, x + 1, y);
+
+                let (facing, lower_x, beyond_x) = match (step_left, step_right) {
+                    (true, true) => {
+                        changes.push((x, y, tile.id, TileShape::Half));
+                        continue;
+                    }
+                    (true, false) => (Facing::Right, x - 1, x - 2),
+                    (false, true) => (Facing::Left, x + 1, x + 2),
+                    (false, false) => continue,
+                };
+
+                // A gentle slope needs the lower ground to carry on for the tile it adds,
+                // and the gap must be wide enough that a slope from the other side can't share it
+                let gentle = rng.gen::<f32>() < GENTLE_SLOPE_CHANCE
+                    && !is_empty(terrain, lower_x, y - 1)
+                    && is_empty(terrain, beyond_x, y)
+                    && terrain.layers[MIDDLE][(lower_x, y)] == Tile::EMPTY;
+
+                if gentle {
+                    changes.push((x, y, tile.id, TileShape::SlopeHigh(facing)));
+                    changes.push((lower_x, y, tile.id, TileShape::SlopeLow(facing)));
+                } else {
+                    changes.push((x, y, tile.id, TileShape::Slope(facing)));
+                }
+            }
+        }
+
+        for (x, y, id, shape) in changes {
+            terrain.layers[FRONT][(x, y)] = Tile {
+                shape,
+                ..Tile::new(id, None)
+            };
+        }
+    }
+}
+
+// Match tiles to their surrounds
+pub struct Autotile;
+
+impl GenerationPass for Autotile {
+    fn name(&self) -> &'static str {
+        "autotile"
+    }
+
+    fn run(&self, terrain: &mut Terrain, _: &mut SipRng) {
+        for layer in 0..TOTAL_LAYERS {
+            if TILESETS[layer].layout.is_none() {
+                continue;
+            }
+
+            // Connections only depend on tile ids, so every offset is found before any is set
+            let mut offsets: Layer<Option<(u32, u32)>> = Layer::new(terrain.width, terrain.height);
+            let mut columns: Vec<_> = offsets.columns_mut().enumerate().collect();
+
+            let terrain_ref = &*terrain;
+
+            for_each_chunk(terrain.parallel, &mut columns, |chunk| {
+                for (x, column) in chunk.iter_mut() {
+                    for (y, offset) in column.iter_mut().enumerate() {
+                        let (x, y) = (*x as u32, y as u32);
+                        let id = terrain_ref.layers[layer][(x, y)].id;
+
+                        *offset = terrain_ref.autotile_offset(id, x, y);
+                    }
+                }
+            });
+
+            for ((x, y), offset) in offsets.iter() {
+                if offset.is_some() {
+                    terrain.layers[layer][(x, y)].texture_offset = *offset;
+                }
+            }
+        }
+    }
+}
+
+pub struct PathTiles;
+
+impl GenerationPass for PathTiles {
+    fn name(&self) -> &'static str {
+        "path_tiles"
+    }
+
+    fn run(&self, terrain: &mut Terrain, _: &mut SipRng) {
+        terrain.generate_path_tiles();
+    }
+}
@@ -0,0 +1,60 @@
+// Saving and loading worlds. Along with every tile, saves store
+// what the world was generated from so it can be regenerated
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Serialize, Deserialize)]
+struct WorldSave {
+    seed: String,
+    settings: GenerationSettings,
+    layers: [Layer<Tile>; TOTAL_LAYERS],
+}
+
+impl Terrain {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let save = WorldSave {
+            seed: self.seed.clone(),
+            settings: self.settings.clone(),
+            layers: self.layers.clone(),
+        };
+
+        let contents = ron::to_string(&save).map_err(|e| format!("Could not save world: {}", e))?;
+
+        fs::write(path, contents).map_err(|e| format!("Could not save world to {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Could not read save {}: {}", path, e))?;
+
+        let save: WorldSave =
+            ron::from_str(&contents).map_err(|e| format!("Invalid save {}: {}", path, e))?;
+
+        let (width, height) = (save.layers[FRONT].width, save.layers[FRONT].height);
+
+        if save
+            .layers
+            .iter()
+            .any(|l| l.width != width || l.height != height)
+        {
+            return Err(format!("Invalid save {}: layers differ in size", path));
+        }
+
+        save.settings
+            .validate()
+            .map_err(|e| format!("Invalid save {}: {}", path, e))?;
+
+        let mut terrain = Terrain::new(save.seed, save.settings, width, height);
+        terrain.layers = save.layers;
+
+        // Path tiles and objects aren't saved as they depend only on the tiles
+        terrain.generate_path_tiles();
+        terrain.find_multi_tiles();
+
+        Ok(terrain)
+    }
+}
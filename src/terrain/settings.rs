@@ -1,65 +1,216 @@
+use std::fs;
+use std::mem::variant_count;
 use std::ops::Range;
 
-#[derive(Default)]
+use serde::{Deserialize, Serialize};
+
+use super::passes::{Pipeline, DEFAULT_PASSES};
+use crate::tile::SurfaceDecor;
+
+// Directory containing settings presets, one RON file per preset
+pub const PRESET_DIR: &str = "assets/worldgen";
+
+const FOREST: &str = include_str!("../../assets/worldgen/forest.ron");
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct GenerationSettings {
     pub surface: SurfaceSettings,
     pub caves: CaveSettings,
     pub decor: DecorSettings,
     pub trees: TreeSettings,
+    pub grass: GrassSettings,
 
-    pub dirt_height: f32,
+    pub dirt_height: f32, // [0; 1] Height at which dirt starts (percentage based on world size)
 
     // Affects the change between stone and dirt
-    pub stone_blur: u32,
-    pub stone_jitter: u32,
+    pub stone_blur: u32,   // [0; 64] No. of tiles over which dirt and stone are mixed
+    pub stone_jitter: u32, // [0; 64] Largest random offset of the dirt height in each column
 
     pub background_offset: u32,
 
-    pub ore_height: f32,
+    pub ore_height: f32, // (0; 1] Maximum height of ore (percentage based on world size)
 
-    // Lower = more frequent ore spawn rates
+    // (0; ..) Lower = more frequent ore spawn rates
     pub ore_rate: u32,
+
+    // Names of the generation passes to run in order, DEFAULT_PASSES if not given
+    #[serde(default)]
+    pub passes: Option<Vec<String>>,
 }
 
 impl GenerationSettings {
-    pub const FOREST: Self = Self {
-        surface: SurfaceSettings {
-            amplitude: 24.0,
-            scale: 2.0,
-            persistence: 0.5,
-            lacunarity: 0.5,
-            octaves: 6,
-            height_offset: 0.75,
-        },
-
-        caves: CaveSettings {
-            solid_density: 0.1,
-            smooth_iters: 4,
-            convert_min: 4,
-            falloff: 2.0,
-        },
-
-        decor: DecorSettings {
-            surface: 0..4,
-            surface_rate: 0.4,
-        },
-
-        trees: TreeSettings {
-            trunk_height_range: 3..5,
-            trunk_variants: 5,
-            spawn_rate: 0.4,
-        },
-
-        dirt_height: 0.60,
-        stone_blur: 18,
-        stone_jitter: 6,
-        background_offset: 2,
-        ore_height: 0.575,
-        ore_rate: 3,
-    };
+    // The default preset, built in so the game runs without the assets directory
+    pub fn forest() -> Self {
+        let settings: Self = ron::from_str(FOREST)
+            .unwrap_or_else(|e| panic!("Invalid built in preset forest: {}", e));
+
+        settings
+            .validate()
+            .unwrap_or_else(|e| panic!("Invalid built in preset forest: {}", e));
+
+        settings
+    }
+
+    // Load a preset by name from PRESET_DIR
+    pub fn load_preset(name: &str) -> Result<Self, String> {
+        let path = format!("{}/{}.ron", PRESET_DIR, name);
+
+        let contents = fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "Could not read preset {}: {}, available presets: {}",
+                path,
+                e,
+                Self::presets().join(", ")
+            )
+        })?;
+
+        let settings: Self =
+            ron::from_str(&contents).map_err(|e| format!("Invalid preset {}: {}", path, e))?;
+
+        settings
+            .validate()
+            .map_err(|e| format!("Invalid preset {}: {}", path, e))?;
+
+        Ok(settings)
+    }
+
+    // Names of all presets in PRESET_DIR
+    pub fn presets() -> Vec<String> {
+        let Ok(entries) = fs::read_dir(PRESET_DIR) else {
+            return Vec::new();
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().map_or(false, |ext| ext == "ron"))
+            .filter_map(|p| p.file_stem()?.to_str().map(String::from))
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    pub fn pipeline(&self) -> Result<Pipeline, String> {
+        match &self.passes {
+            Some(names) => Pipeline::from_names(names),
+            None => Pipeline::from_names(&DEFAULT_PASSES),
+        }
+    }
+
+    // Checks that every setting is within its documented range
+    pub fn validate(&self) -> Result<(), String> {
+        let unit = |v: f32| (0.0..=1.0).contains(&v);
+
+        let checks = [
+            (self.surface.scale > 0.0, "surface.scale", "above 0"),
+            (self.surface.octaves > 0, "surface.octaves", "above 0"),
+            (
+                unit(self.surface.height_offset),
+                "surface.height_offset",
+                "in [0; 1]",
+            ),
+            (
+                (-1.0..=1.0).contains(&self.caves.solid_density),
+                "caves.solid_density",
+                "in [-1; 1]",
+            ),
+            (
+                (1..=8).contains(&self.caves.smooth_iters),
+                "caves.smooth_iters",
+                "in (0; 8]",
+            ),
+            (
+                self.caves.convert_min <= 8,
+                "caves.convert_min",
+                "in [0; 8]",
+            ),
+            (self.caves.falloff >= 0.0, "caves.falloff", "at least 0"),
+            (self.caves.worms <= 16, "caves.worms", "in [0; 16]"),
+            (self.caves.worm_radius > 0.0, "caves.worm_radius", "above 0"),
+            (
+                (0.0..=std::f32::consts::PI).contains(&self.caves.worm_turn),
+                "caves.worm_turn",
+                "in [0; pi]",
+            ),
+            (
+                unit(self.caves.cavern_height.start)
+                    && unit(self.caves.cavern_height.end)
+                    && !self.caves.cavern_height.is_empty(),
+                "caves.cavern_height",
+                "a non-empty range in [0; 1]",
+            ),
+            (
+                self.caves.cavern_size.start > 0 && !self.caves.cavern_size.is_empty(),
+                "caves.cavern_size",
+                "a non-empty range above 0",
+            ),
+            (
+                !self.decor.surface.is_empty()
+                    && self.decor.surface.end <= variant_count::<SurfaceDecor>(),
+                "decor.surface",
+                "a non-empty range of SurfaceDecor variants",
+            ),
+            (
+                unit(self.decor.surface_rate),
+                "decor.surface_rate",
+                "in [0; 1]",
+            ),
+            (
+                !self.trees.trunk_height_range.is_empty(),
+                "trees.trunk_height_range",
+                "non-empty",
+            ),
+            (
+                self.trees.trunk_variants >= 2,
+                "trees.trunk_variants",
+                "at least 2",
+            ),
+            (unit(self.trees.spawn_rate), "trees.spawn_rate", "in [0; 1]"),
+            (
+                unit(self.grass.spread_chance),
+                "grass.spread_chance",
+                "in [0; 1]",
+            ),
+            (
+                unit(self.grass.decay_chance),
+                "grass.decay_chance",
+                "in [0; 1]",
+            ),
+            (
+                unit(self.grass.decor_chance),
+                "grass.decor_chance",
+                "in [0; 1]",
+            ),
+            (unit(self.dirt_height), "dirt_height", "in [0; 1]"),
+            (self.stone_blur <= 64, "stone_blur", "in [0; 64]"),
+            (self.stone_jitter <= 64, "stone_jitter", "in [0; 64]"),
+            (
+                self.ore_height > 0.0 && self.ore_height <= 1.0,
+                "ore_height",
+                "in (0; 1]",
+            ),
+            (self.ore_rate > 0, "ore_rate", "above 0"),
+        ];
+
+        for (valid, name, expected) in checks {
+            if !valid {
+                return Err(format!(
+                    "{} must be {}, found: {}",
+                    name,
+                    expected,
+                    self.get_field(name).unwrap_or_default()
+                ));
+            }
+        }
+
+        self.pipeline()?;
+
+        Ok(())
+    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct SurfaceSettings {
     // Perlin noise values
     pub scale: f32,
@@ -71,23 +222,302 @@ pub struct SurfaceSettings {
     pub height_offset: f32, // Offset at which height will start [0; 1] (percentage based on world size)
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct CaveSettings {
     pub solid_density: f32, // [-1; 1] Density at which a tile is considered solid
     pub smooth_iters: u32,  // (0; 8] No. of times to smooth cave gen
     pub convert_min: u32,   // [0; 8] No. of like neighbours required to convert a tile
-    pub falloff: f32,
+    pub falloff: f32,       // [0; ..) How much denser the ground gets towards the surface
+
+    // Tunnels dug down from the surface
+    pub worms: u32,       // [0; 16] No. of worms per chunk
+    pub worm_length: u32, // No. of tiles each worm travels
+    pub worm_radius: f32, // (0; ..) Average radius of worm tunnels
+    pub worm_turn: f32,   // [0; pi] Largest angle a worm can turn away from straight down
+
+    // Large open caves
+    pub caverns: u32,              // No. of caverns per chunk
+    pub cavern_height: Range<f32>, // [0; 1] Heights caverns are placed between (percentage)
+    pub cavern_size: Range<u32>,   // (0; ..) Vertical radii, caverns are twice as wide
+
+    // [0; ..) Caves with at least this many tiles are connected to the surface
+    pub min_region: u32,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct DecorSettings {
-    pub surface: Range<usize>, // Single tile decor
+    pub surface: Range<usize>, // SurfaceDecor variants to choose from, by index
     pub surface_rate: f32,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct TreeSettings {
     pub trunk_height_range: Range<u32>, // Possible sizes of tree trunks
-    pub trunk_variants: u32,            // Different trunk tile variants
+    pub trunk_variants: u32,            // [2; ..) Different trunk tile variants
     pub spawn_rate: f32,
 }
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct GrassSettings {
+    pub ticks: u32,         // No. of random tiles visited per chunk each frame
+    pub spread_chance: f32, // [0; 1] Chance grass spreads to an exposed dirt neighbour
+    pub decay_chance: f32,  // [0; 1] Chance covered grass reverts to dirt
+    pub decor_chance: f32,  // [0; 1] Chance decor sprouts on exposed grass
+}
+
+// Conversion between settings and text so they can be changed by name
+pub trait Setting: Sized {
+    fn to_setting(&self) -> String;
+    fn from_setting(value: &str) -> Result<Self, String>;
+
+    // Step the value up or down, used by the editor
+    fn nudge(&mut self, steps: i32);
+}
+
+macro_rules! impl_int_setting {
+    ($($t:ty),*) => {
+        $(
+            impl Setting for $t {
+                fn to_setting(&self) -> String {
+                    self.to_string()
+                }
+
+                fn from_setting(value: &str) -> Result<Self, String> {
+                    value
+                        .parse()
+                        .map_err(|_| format!("Expected a {}, found: {}", stringify!($t), value))
+                }
+
+                fn nudge(&mut self, steps: i32) {
+                    *self = (*self as i64 + steps as i64).max(0) as $t;
+                }
+            }
+        )*
+    };
+}
+
+impl_int_setting!(u32, usize);
+
+impl Setting for f32 {
+    fn to_setting(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_setting(value: &str) -> Result<Self, String> {
+        value
+            .parse()
+            .map_err(|_| format!("Expected a f32, found: {}", value))
+    }
+
+    fn nudge(&mut self, steps: i32) {
+        // Rounded to keep values readable
+        *self = ((*self + steps as f32 * 0.05) * 100.0).round() / 100.0;
+    }
+}
+
+// Ranges are written as start..end, nudging moves the end
+impl<T: Setting + Copy + PartialOrd> Setting for Range<T> {
+    fn to_setting(&self) -> String {
+        format!("{}..{}", self.start.to_setting(), self.end.to_setting())
+    }
+
+    fn from_setting(value: &str) -> Result<Self, String> {
+        let (start, end) = value
+            .split_once("..")
+            .ok_or_else(|| format!("Expected a range (a..b), found: {}", value))?;
+
+        Ok(T::from_setting(start)?..T::from_setting(end)?)
+    }
+
+    fn nudge(&mut self, steps: i32) {
+        let end = self.end;
+        self.end.nudge(steps);
+
+        // Ranges must not be empty
+        if self.end <= self.start {
+            self.end = end;
+        }
+    }
+}
+
+// Generates accessors for every named setting
+macro_rules! settings_fields {
+    ($($name:literal => $($field:ident).+),* $(,)?) => {
+        impl GenerationSettings {
+            pub const FIELDS: &'static [&'static str] = &[$($name),*];
+
+            pub fn get_field(&self, name: &str) -> Option<String> {
+                match name {
+                    $($name => Some(self.$($field).+.to_setting()),)*
+                    _ => None,
+                }
+            }
+
+            // The setting is left unchanged if the new value is out of range
+            pub fn set_field(&mut self, name: &str, value: &str) -> Result<(), String> {
+                let mut settings = self.clone();
+
+                match name {
+                    $($name => settings.$($field).+ = Setting::from_setting(value)?,)*
+                    _ => return Err(format!("Unknown setting: {}", name)),
+                }
+
+                settings.validate()?;
+                *self = settings;
+
+                Ok(())
+            }
+
+            pub fn nudge_field(&mut self, name: &str, steps: i32) {
+                let mut settings = self.clone();
+
+                match name {
+                    $($name => settings.$($field).+.nudge(steps),)*
+                    _ => (),
+                }
+
+                if settings.validate().is_ok() {
+                    *self = settings;
+                }
+            }
+        }
+    };
+}
+
+settings_fields! {
+    "surface.scale" => surface.scale,
+    "surface.amplitude" => surface.amplitude,
+    "surface.persistence" => surface.persistence,
+    "surface.lacunarity" => surface.lacunarity,
+    "surface.octaves" => surface.octaves,
+    "surface.height_offset" => surface.height_offset,
+    "caves.solid_density" => caves.solid_density,
+    "caves.smooth_iters" => caves.smooth_iters,
+    "caves.convert_min" => caves.convert_min,
+    "caves.falloff" => caves.falloff,
+    "caves.worms" => caves.worms,
+    "caves.worm_length" => caves.worm_length,
+    "caves.worm_radius" => caves.worm_radius,
+    "caves.worm_turn" => caves.worm_turn,
+    "caves.caverns" => caves.caverns,
+    "caves.cavern_height" => caves.cavern_height,
+    "caves.cavern_size" => caves.cavern_size,
+    "caves.min_region" => caves.min_region,
+    "decor.surface" => decor.surface,
+    "decor.surface_rate" => decor.surface_rate,
+    "trees.trunk_height_range" => trees.trunk_height_range,
+    "trees.trunk_variants" => trees.trunk_variants,
+    "trees.spawn_rate" => trees.spawn_rate,
+    "grass.ticks" => grass.ticks,
+    "grass.spread_chance" => grass.spread_chance,
+    "grass.decay_chance" => grass.decay_chance,
+    "grass.decor_chance" => grass.decor_chance,
+    "dirt_height" => dirt_height,
+    "stone_blur" => stone_blur,
+    "stone_jitter" => stone_jitter,
+    "background_offset" => background_offset,
+    "ore_height" => ore_height,
+    "ore_rate" => ore_rate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid() {
+        assert!(GenerationSettings::forest().validate().is_ok());
+
+        for name in GenerationSettings::presets() {
+            if let Err(e) = GenerationSettings::load_preset(&name) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_settings_are_rejected() {
+        let invalid = [
+            ("surface.scale", "0"),
+            ("surface.octaves", "0"),
+            ("surface.height_offset", "1.5"),
+            ("caves.solid_density", "-2"),
+            ("caves.smooth_iters", "0"),
+            ("caves.smooth_iters", "9"),
+            ("caves.convert_min", "9"),
+            ("caves.falloff", "-1"),
+            ("caves.worms", "17"),
+            ("caves.cavern_height", "0.5..0.2"),
+            ("caves.cavern_size", "0..4"),
+            ("decor.surface", "2..2"),
+            ("decor.surface", "0..10"),
+            ("trees.trunk_variants", "1"),
+            ("grass.decay_chance", "1.1"),
+            ("dirt_height", "-0.1"),
+            ("stone_blur", "65"),
+            ("stone_jitter", "65"),
+            ("ore_height", "0"),
+            ("ore_rate", "0"),
+        ];
+
+        for (name, value) in invalid {
+            let mut settings = GenerationSettings::forest();
+            let error = settings.set_field(name, value).unwrap_err();
+
+            assert!(error.starts_with(name), "{} = {}: {}", name, value, error);
+
+            // The old value is kept
+            assert_eq!(
+                settings.get_field(name),
+                GenerationSettings::forest().get_field(name)
+            );
+        }
+    }
+
+    #[test]
+    fn nudging_stays_in_range() {
+        let mut settings = GenerationSettings::forest();
+
+        for _ in 0..20 {
+            settings.nudge_field("trees.trunk_variants", -1);
+            settings.nudge_field("grass.spread_chance", 1);
+        }
+
+        assert_eq!(settings.trees.trunk_variants, 2);
+        assert_eq!(settings.grass.spread_chance, 1.0);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn range_limits_are_valid() {
+        let valid = [
+            ("surface.height_offset", "0"),
+            ("surface.height_offset", "1"),
+            ("caves.solid_density", "-1"),
+            ("caves.smooth_iters", "8"),
+            ("caves.convert_min", "0"),
+            ("caves.falloff", "0"),
+            ("caves.worms", "16"),
+            ("decor.surface", "0..4"),
+            ("trees.trunk_variants", "2"),
+            ("stone_blur", "64"),
+            ("stone_jitter", "0"),
+            ("ore_height", "1"),
+        ];
+
+        for (name, value) in valid {
+            let mut settings = GenerationSettings::forest();
+
+            let result = settings.set_field(name, value);
+            assert!(result.is_ok(), "{} = {}", name, value);
+        }
+    }
+
+    #[test]
+    fn unknown_passes_are_rejected() {
+        let mut settings = GenerationSettings::forest();
+        settings.passes = Some(vec!["surface".to_string(), "lava".to_string()]);
+
+        assert!(settings.validate().is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use std::f32::INFINITY;
 
 use num_derive::FromPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::terrain::{BACK, FRONT, MIDDLE};
 
 pub const TILESET_SIZE: (u32, u32) = (22, 16);
 
 // Attached to every tile, used for identification
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TileId {
     #[default]
     Null, // Should never be present in a functioning world
@@ -18,26 +21,41 @@ pub enum TileId {
     Tree(Tree),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+impl TileId {
+    // The layer a tile is placed on
+    pub fn layer(&self) -> usize {
+        match self {
+            TileId::Background(_) => BACK,
+            TileId::SurfaceDecor(_) | TileId::Tree(_) => MIDDLE,
+            _ => FRONT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Ground {
     Grass,
     Dirt,
     Stone,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive, Serialize, Deserialize,
+)]
 pub enum Ore {
     Iron,
     Gold,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Background {
     Dirt,
     Stone,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive, Serialize, Deserialize,
+)]
 pub enum SurfaceDecor {
     GrassSmall,
     Rock,
@@ -45,36 +63,140 @@ pub enum SurfaceDecor {
     RockPile,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive, Serialize, Deserialize,
+)]
 pub enum Tree {
     Wood,
     Foliage,
 }
 
 // Used during world creation and in save files
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Tile {
     pub id: TileId,
 
     // Offset from the top left corner of the multi-tile
     pub texture_offset: Option<(u32, u32)>,
+
+    // Left out of saves for full tiles, which are almost all of them
+    #[serde(default, skip_serializing_if = "TileShape::is_full")]
+    pub shape: TileShape,
+}
+
+// The side of a tile a slope rises towards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Facing {
+    Left,
+    Right,
+}
+
+// Shapes FRONT tiles can have other than a full block
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TileShape {
+    #[default]
+    Full,
+    Half, // The bottom half of the tile
+
+    Slope(Facing),     // 45 degrees, across one tile
+    SlopeLow(Facing),  // 22.5 degrees, the lower of the two tiles
+    SlopeHigh(Facing), // 22.5 degrees, the upper of the two tiles
+}
+
+impl TileShape {
+    // Every shape other than Full
+    pub const SHAPED: [Self; 7] = [
+        TileShape::Half,
+        TileShape::Slope(Facing::Left),
+        TileShape::Slope(Facing::Right),
+        TileShape::SlopeLow(Facing::Left),
+        TileShape::SlopeLow(Facing::Right),
+        TileShape::SlopeHigh(Facing::Left),
+        TileShape::SlopeHigh(Facing::Right),
+    ];
+
+    pub fn is_full(&self) -> bool {
+        *self == TileShape::Full
+    }
+
+    // Cell of the shape in the tile's tileset cells
+    pub fn cell(&self) -> Option<(u32, u32)> {
+        let x = match self {
+            TileShape::Full => return None,
+            TileShape::Half => 7,
+            TileShape::Slope(Facing::Right) => 8,
+            TileShape::Slope(Facing::Left) => 9,
+            TileShape::SlopeLow(Facing::Right) => 10,
+            TileShape::SlopeHigh(Facing::Right) => 11,
+            TileShape::SlopeLow(Facing::Left) => 12,
+            TileShape::SlopeHigh(Facing::Left) => 13,
+        };
+
+        Some((x, 2))
+    }
+
+    // Height of the left and right sides of the top, as a fraction of a tile
+    fn heights(&self) -> (f32, f32) {
+        let (heights, facing) = match *self {
+            TileShape::Full => return (1.0, 1.0),
+            TileShape::Half => return (0.5, 0.5),
+            TileShape::Slope(facing) => ((0.0, 1.0), facing),
+            TileShape::SlopeLow(facing) => ((0.0, 0.5), facing),
+            TileShape::SlopeHigh(facing) => ((0.5, 1.0), facing),
+        };
+
+        match facing {
+            Facing::Right => heights,
+            Facing::Left => (heights.1, heights.0),
+        }
+    }
+
+    // Corners of the shape, anticlockwise from the bottom left, with (0, 0) at the
+    // bottom left of the tile and (1, 1) at the top right
+    pub fn outline(&self) -> Vec<(f32, f32)> {
+        let (left, right) = self.heights();
+        let mut points = vec![(0.0, 0.0), (1.0, 0.0)];
+
+        if right > 0.0 {
+            points.push((1.0, right));
+        }
+
+        if left > 0.0 {
+            points.push((0.0, left));
+        }
+
+        points
+    }
+
+    // Whether something can walk onto this tile from a tile one lower on the given side
+    pub fn eases_from(&self, side: Facing) -> bool {
+        let (left, right) = self.heights();
+
+        match side {
+            Facing::Left => left < 1.0,
+            Facing::Right => right < 1.0,
+        }
+    }
 }
 
 impl Tile {
     pub const NULL: Self = Self {
         id: TileId::Null,
         texture_offset: None,
+        shape: TileShape::Full,
     };
 
     pub const EMPTY: Self = Self {
         id: TileId::Empty,
         texture_offset: None,
+        shape: TileShape::Full,
     };
 
     pub fn new(id: TileId, offset: Option<(u32, u32)>) -> Self {
         Self {
             id,
             texture_offset: offset,
+            shape: TileShape::Full,
         }
     }
 
@@ -82,7 +204,8 @@ impl Tile {
     pub fn get_texture_index(&self) -> u32 {
         let mut index = TileDescriptor::from_id(self.id).tileset_position;
 
-        if let Some(offset) = self.texture_offset {
+        // Shaped tiles have one sprite whatever their surroundings
+        if let Some(offset) = self.shape.cell().or(self.texture_offset) {
             index += offset.1 * TILESET_SIZE.0 + offset.0
         }
 
@@ -90,16 +213,170 @@ impl Tile {
     }
 }
 
+// The shape of the veins an ore generates in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VeinShape {
+    Blob,      // Round clusters
+    Streak,    // Long thin veins that wander
+    Scattered, // Single tiles spread over an area
+}
+
 // Describes ore-specific properties
 #[derive(Copy, Clone)]
 pub struct OreDescriptor {
+    // [0; 1] Heights the ore generates between (percentage of the settings' ore_height)
+    pub min_height: f32,
     pub max_height: f32,
-    pub radius: u32,
+
+    // (0; ..) Chance of a vein being this ore, relative to the other ores
+    pub frequency: f32,
+
+    pub shape: VeinShape,
+
+    // Radius of blobs, length of streaks and no. of scattered tiles
+    pub size: u32,
+
+    // Tiles the ore can replace
+    pub hosts: &'static [TileId],
+}
+
+// An alternative sprite for one autotile configuration, picked by the tile's position
+pub struct Variant {
+    // The configuration it replaces, as Surrounds flag names
+    pub config: &'static str,
+
+    // Cell to use instead, from the tile's first cell. None keeps the usual cell
+    pub cell: Option<(u32, u32)>,
+
+    pub flip_x: bool,
+    pub flip_y: bool,
+
+    // Chance of being used, relative to the usual sprite's USUAL_WEIGHT
+    pub weight: u32,
+}
+
+impl Variant {
+    const fn flipped(config: &'static str, flip_x: bool, flip_y: bool) -> Self {
+        Self {
+            config,
+            cell: None,
+            flip_x,
+            flip_y,
+            weight: 1,
+        }
+    }
+
+    const fn cell(config: &'static str, cell: (u32, u32)) -> Self {
+        Self {
+            config,
+            cell: Some(cell),
+            flip_x: false,
+            flip_y: false,
+            weight: 1,
+        }
+    }
+}
+
+pub const USUAL_WEIGHT: u32 = 4;
+
+// Mirrored interiors and flat surfaces, which look right either way round
+const FLIPPED_VARIANTS: &[Variant] = &[
+    Variant::flipped("TL TM TR ML MR BL BM BR", true, false),
+    Variant::flipped("TL TM TR ML MR BL BM BR", false, true),
+    Variant::flipped("TL TM TR ML MR BL BM BR", true, true),
+    Variant::flipped("ML MR BL BM BR", true, false),
+    Variant::flipped("TL TM TR ML MR", true, false),
+];
+
+// Interiors with a pebble and a root, drawn in the spare cells of dirt's last row
+const DIRT_VARIANTS: &[Variant] = &[
+    Variant::flipped("TL TM TR ML MR BL BM BR", true, false),
+    Variant::flipped("TL TM TR ML MR BL BM BR", false, true),
+    Variant::flipped("TL TM TR ML MR BL BM BR", true, true),
+    Variant::flipped("ML MR BL BM BR", true, false),
+    Variant::flipped("TL TM TR ML MR", true, false),
+    Variant::cell("TL TM TR ML MR BL BM BR", (14, 2)),
+    Variant::cell("TL TM TR ML MR BL BM BR", (15, 2)),
+];
+
+// Cracked interiors, drawn in the same cells as dirt's
+const STONE_VARIANTS: &[Variant] = &[
+    Variant::flipped("TL TM TR ML MR BL BM BR", true, false),
+    Variant::flipped("TL TM TR ML MR BL BM BR", false, true),
+    Variant::flipped("TL TM TR ML MR BL BM BR", true, true),
+    Variant::flipped("ML MR BL BM BR", true, false),
+    Variant::flipped("TL TM TR ML MR", true, false),
+    Variant::cell("TL TM TR ML MR BL BM BR", (14, 2)),
+    Variant::cell("TL TM TR ML MR BL BM BR", (15, 2)),
+];
+
+// A cell of the tileset and how it is flipped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    pub index: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+// Tiles join up with other tiles in the same group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectGroup {
+    None, // Never joins up with anything
+    Soil,
+    Rock,
+    Ore,
+    Wall,
+    Plant,
+}
+
+// Sprites for the edges a tile shows against another group, instead of against nothing
+pub struct Transition {
+    pub group: ConnectGroup,
+
+    // From the tile's first cell to the first of its transition cells, which use the edges layout
+    pub offset: (u32, u32),
+}
+
+pub struct Connections {
+    pub group: ConnectGroup,
+
+    // Groups that are joined up with, from both sides, eg. ores and the rock they are found in
+    pub connects_to: &'static [ConnectGroup],
+
+    // Groups that this tile runs into without them running back, so only they show an edge
+    pub blends_into: &'static [ConnectGroup],
+
+    // Used when every edge the tile shows is against this group
+    pub transition: Option<Transition>,
+
+    // FRONT tiles also count walls in the BACK layer as neighbours
+    pub connects_behind: bool,
+}
+
+impl Connections {
+    pub const NONE: Self = Self {
+        group: ConnectGroup::None,
+        connects_to: &[],
+        blends_into: &[],
+        transition: None,
+        connects_behind: false,
+    };
+
+    pub const fn group(group: ConnectGroup) -> Self {
+        Self {
+            group,
+            ..Self::NONE
+        }
+    }
 }
 
 // Contains a description of every tile
 pub struct TileDescriptor {
     pub id: TileId,
+
+    // Used to refer to the tile in text, eg: the console
+    pub name: &'static str,
+
     pub tileset_position: u32,
 
     // Used for structures that take up more than one tile
@@ -108,8 +385,20 @@ pub struct TileDescriptor {
     // Describes ore related properties
     pub ore: Option<OreDescriptor>,
 
+    // How the tile joins up with its neighbours when auto-tiled
+    pub connections: Connections,
+
+    // Alternative sprites, to make large areas look less repetitive
+    pub variants: &'static [Variant],
+
+    // Whether the tile can be made into slopes and half tiles
+    pub shaped: bool,
+
     // Basic stats
     pub hardness: f32,
+
+    // RGBA colour used to draw this tile on the map
+    pub map_colour: [u8; 4],
 }
 
 impl TileDescriptor {
@@ -124,118 +413,253 @@ impl TileDescriptor {
         panic!("Error, TileDescriptor missing for tile: {:?}", id);
     }
 
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        Self::all().iter().find(|desc| desc.name == name)
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::all().iter().map(|desc| desc.name)
+    }
+
+    pub fn all() -> &'static [Self] {
+        &Self::DESCRIPTORS
+    }
+
+    // Whether this tile joins up with a neighbour instead of showing an edge
+    pub fn connects(&self, other: &Self) -> bool {
+        let (a, b) = (&self.connections, &other.connections);
+
+        if a.group == ConnectGroup::None || b.group == ConnectGroup::None {
+            return false;
+        }
+
+        a.group == b.group
+            || a.connects_to.contains(&b.group)
+            || b.connects_to.contains(&a.group)
+            || a.blends_into.contains(&b.group)
+    }
+
+    // Every tile that generates as an ore
+    pub fn ores() -> impl Iterator<Item = (&'static Self, &'static OreDescriptor)> {
+        Self::all()
+            .iter()
+            .filter_map(|desc| desc.ore.as_ref().map(|ore| (desc, ore)))
+    }
+
     // Array of all tiles in the game, this will be replaced with a file
     const DESCRIPTORS: [Self; 15] = [
         Self {
             id: TileId::Null,
+            name: "null",
             tileset_position: TILESET_SIZE.0 * TILESET_SIZE.1 - 1,
             dimensions: None,
             hardness: INFINITY,
+            map_colour: [255, 0, 255, 255],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::Empty,
+            name: "empty",
             tileset_position: TILESET_SIZE.0 * TILESET_SIZE.1 - 2,
             dimensions: None,
             hardness: 0.0,
+            map_colour: [0, 0, 0, 0],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::Ground(Ground::Grass),
+            name: "grass",
             tileset_position: 3 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [84, 160, 60, 255],
             ore: None,
+            connections: Connections {
+                group: ConnectGroup::Soil,
+                blends_into: &[ConnectGroup::Rock],
+                ..Connections::NONE
+            },
+            variants: FLIPPED_VARIANTS,
+            shaped: true,
         },
         Self {
             id: TileId::Ground(Ground::Dirt),
+            name: "dirt",
             tileset_position: 0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [120, 84, 52, 255],
             ore: None,
+            connections: Connections {
+                group: ConnectGroup::Soil,
+                connects_to: &[ConnectGroup::Ore],
+                blends_into: &[ConnectGroup::Rock],
+                ..Connections::NONE
+            },
+            variants: DIRT_VARIANTS,
+            shaped: true,
         },
         Self {
             id: TileId::Ground(Ground::Stone),
+            name: "stone",
             tileset_position: 6 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [110, 110, 116, 255],
             ore: None,
+            connections: Connections {
+                group: ConnectGroup::Rock,
+                connects_to: &[ConnectGroup::Ore],
+
+                // Dirt runs under the stone's edges, drawn in row 15
+                transition: Some(Transition {
+                    group: ConnectGroup::Soil,
+                    offset: (0, 9),
+                }),
+                ..Connections::NONE
+            },
+            variants: STONE_VARIANTS,
+            shaped: true,
         },
         Self {
             id: TileId::Ore(Ore::Iron),
+            name: "iron",
             tileset_position: 9 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [176, 136, 112, 255],
             ore: Some(OreDescriptor {
+                min_height: 0.0,
                 max_height: 1.0,
-                radius: 4,
+                frequency: 3.0,
+                shape: VeinShape::Blob,
+                size: 4,
+                hosts: &[TileId::Ground(Ground::Stone), TileId::Ground(Ground::Dirt)],
             }),
+            connections: Connections::group(ConnectGroup::Ore),
+            variants: FLIPPED_VARIANTS,
+            shaped: false,
         },
         Self {
             id: TileId::Ore(Ore::Gold),
+            name: "gold",
             tileset_position: 12 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [224, 188, 60, 255],
             ore: Some(OreDescriptor {
+                min_height: 0.0,
                 max_height: 0.50,
-                radius: 3,
+                frequency: 1.0,
+                shape: VeinShape::Streak,
+                size: 12,
+                hosts: &[TileId::Ground(Ground::Stone)],
             }),
+            connections: Connections::group(ConnectGroup::Ore),
+            variants: FLIPPED_VARIANTS,
+            shaped: false,
         },
         Self {
             id: TileId::Background(Background::Dirt),
+            name: "background_dirt",
             tileset_position: 0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [60, 42, 26, 255],
             ore: None,
+            connections: Connections::group(ConnectGroup::Wall),
+            variants: FLIPPED_VARIANTS,
+            shaped: false,
         },
         Self {
             id: TileId::Background(Background::Stone),
+            name: "background_stone",
             tileset_position: 3 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [55, 55, 58, 255],
             ore: None,
+            connections: Connections::group(ConnectGroup::Wall),
+            variants: FLIPPED_VARIANTS,
+            shaped: false,
         },
         Self {
             id: TileId::SurfaceDecor(SurfaceDecor::GrassSmall),
-            tileset_position: 1 * TILESET_SIZE.0,
+            name: "grass_small",
+            tileset_position: 7 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [96, 176, 68, 255],
             ore: None,
+            connections: Connections::group(ConnectGroup::Plant),
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::SurfaceDecor(SurfaceDecor::GrassMedium),
+            name: "grass_medium",
             tileset_position: 1,
             dimensions: Some((1, 2)),
             hardness: 1.0,
+            map_colour: [96, 176, 68, 255],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::SurfaceDecor(SurfaceDecor::Rock),
+            name: "rock",
             tileset_position: 3 * TILESET_SIZE.0,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [128, 128, 132, 255],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::SurfaceDecor(SurfaceDecor::RockPile),
+            name: "rock_pile",
             tileset_position: 3 * TILESET_SIZE.0 + 1,
             dimensions: Some((2, 1)),
             hardness: 1.0,
+            map_colour: [128, 128, 132, 255],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::Tree(Tree::Wood),
+            name: "wood",
             tileset_position: 17,
             dimensions: None,
             hardness: 1.0,
+            map_colour: [100, 70, 40, 255],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
         Self {
             id: TileId::Tree(Tree::Foliage),
+            name: "foliage",
             tileset_position: 1 * TILESET_SIZE.0 + 17,
             dimensions: Some((5, 6)),
             hardness: 1.0,
+            map_colour: [48, 120, 48, 255],
             ore: None,
+            connections: Connections::NONE,
+            variants: &[],
+            shaped: false,
         },
     ];
 }